@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::function_index::FunctionIndex;
+use crate::recovery::{self, RecoveredFile};
+use crate::vfs::CrateFiles;
+
+/// 一个文件读取与解析的结果，以及其中函数的行范围索引
+pub struct ParsedSource {
+    pub source: String,
+    pub parsed: RecoveredFile,
+    pub functions: FunctionIndex,
+}
+
+/// 当前 crate 中已读取并解析的文件，同一个文件的记录共用一次解析；切换 crate 时清空
+#[derive(Default)]
+pub struct AstCache {
+    /// 相对路径 -> 解析结果，读取失败时保存失败原因
+    files: HashMap<PathBuf, Result<ParsedSource, String>>,
+    lookups: usize,
+    parsed_files: usize,
+}
+
+impl AstCache {
+    pub fn clear(&mut self) {
+        self.files.clear();
+    }
+
+    /// 读取并解析 crate 中的 rel_file，每个文件只读取、解析一次，读取失败也会缓存
+    pub fn get_or_parse(&mut self, crate_files: &CrateFiles, rel_file: &Path) -> Result<&ParsedSource, &str> {
+        self.lookups += 1;
+        if !self.files.contains_key(rel_file) {
+            self.parsed_files += 1;
+            let parsed_source = crate_files
+                .read_to_string(rel_file)
+                .map(|source| {
+                    let parsed = recovery::parse_tolerant(&source);
+                    ParsedSource {
+                        functions: FunctionIndex::build(&parsed.file),
+                        source,
+                        parsed,
+                    }
+                })
+                .map_err(|e| e.to_string());
+            self.files.insert(rel_file.to_path_buf(), parsed_source);
+        }
+        match &self.files[rel_file] {
+            Ok(parsed_source) => Ok(parsed_source),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 本次运行中查找文件的次数与实际解析的文件数
+    pub fn stats(&self) -> (usize, usize) {
+        (self.lookups, self.parsed_files)
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// crates.io index 中一行版本记录里用到的字段
+#[derive(Debug, Deserialize)]
+struct IndexLine {
+    name: String,
+    vers: String,
+    cksum: String,
+}
+
+/// 校验失败的原因，调用方据此与其他打开 crate 的错误区分
+#[derive(Debug)]
+pub enum ChecksumError {
+    Missing { crate_name: String, version: String },
+    Mismatch { archive: PathBuf, expected: String, actual: String },
+}
+
+impl fmt::Display for ChecksumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChecksumError::Missing { crate_name, version } => {
+                write!(f, "crate {} version {} has no checksum in the crate index", crate_name, version)
+            }
+            ChecksumError::Mismatch {
+                archive,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "checksum mismatch for {:?}: index has {}, archive is {}",
+                archive, expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ChecksumError {}
+
+/// 本地 crates.io index 快照，提供每个版本 .crate 的 SHA-256
+///
+/// 可以是 index 仓库的 checkout（`1/a`、`3/s/syn`、`se/rd/serde` 这样的目录结构），
+/// 也可以是把所有版本记录放在一起的单个 JSON Lines 文件。
+#[derive(Debug)]
+pub struct ChecksumIndex {
+    checkout: Option<PathBuf>,
+    /// 小写 crate 名 -> 版本 -> cksum；checkout 中的 crate 在第一次查找时载入
+    loaded: HashMap<String, HashMap<String, String>>,
+}
+
+impl ChecksumIndex {
+    pub fn open(path: &Path) -> Result<ChecksumIndex> {
+        if path.is_dir() {
+            return Ok(ChecksumIndex {
+                checkout: Some(path.to_path_buf()),
+                loaded: HashMap::new(),
+            });
+        }
+        let mut index = ChecksumIndex {
+            checkout: None,
+            loaded: HashMap::new(),
+        };
+        let file = File::open(path).with_context(|| format!("cannot open crate index {:?}", path))?;
+        index.load_lines(BufReader::new(file), path)?;
+        println!("loaded checksums of {} crates from {:?}", index.loaded.len(), path);
+        Ok(index)
+    }
+
+    /// 校验 archive 的 SHA-256 与 index 中记录的 cksum 一致，返回校验过的 cksum
+    pub fn verify(&mut self, archive: &Path, crate_name: &str, version: &str) -> Result<String> {
+        let expected = match self.expected(crate_name, version)? {
+            Some(expected) => expected,
+            None => {
+                return Err(ChecksumError::Missing {
+                    crate_name: crate_name.to_owned(),
+                    version: version.to_owned(),
+                }
+                .into())
+            }
+        };
+        let actual = sha256_file(archive)?;
+        if !actual.eq_ignore_ascii_case(&expected) {
+            return Err(ChecksumError::Mismatch {
+                archive: archive.to_path_buf(),
+                expected,
+                actual,
+            }
+            .into());
+        }
+        Ok(actual)
+    }
+
+    fn expected(&mut self, crate_name: &str, version: &str) -> Result<Option<String>> {
+        let key = crate_name.to_ascii_lowercase();
+        if let Some(checkout) = &self.checkout {
+            if !self.loaded.contains_key(&key) {
+                let path = checkout.join(index_path(&key));
+                match File::open(&path) {
+                    Ok(file) => self.load_lines(BufReader::new(file), &path)?,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e).with_context(|| format!("cannot open crate index file {:?}", path)),
+                }
+                // 找不到的 crate 也记下来，避免重复打开文件
+                self.loaded.entry(key.clone()).or_default();
+            }
+        }
+        Ok(self.loaded.get(&key).and_then(|versions| versions.get(version)).cloned())
+    }
+
+    fn load_lines(&mut self, reader: impl BufRead, path: &Path) -> Result<()> {
+        for (line_index, line) in reader.lines().enumerate() {
+            let line = line.with_context(|| format!("cannot read crate index {:?}", path))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: IndexLine = serde_json::from_str(&line)
+                .with_context(|| format!("invalid crate index line {} in {:?}", line_index + 1, path))?;
+            self.loaded
+                .entry(entry.name.to_ascii_lowercase())
+                .or_default()
+                .insert(entry.vers, entry.cksum);
+        }
+        Ok(())
+    }
+}
+
+/// crates.io index 仓库中 crate 文件的相对路径
+fn index_path(lowercase_name: &str) -> PathBuf {
+    match lowercase_name.len() {
+        1 => Path::new("1").join(lowercase_name),
+        2 => Path::new("2").join(lowercase_name),
+        3 => Path::new("3").join(&lowercase_name[..1]).join(lowercase_name),
+        _ => Path::new(&lowercase_name[..2]).join(&lowercase_name[2..4]).join(lowercase_name),
+    }
+}
+
+/// 文件内容的 SHA-256，小写十六进制
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path).with_context(|| format!("cannot open file {:?}", path))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).with_context(|| format!("cannot read file {:?}", path))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -0,0 +1,188 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::crate_source::CacheLayout;
+use crate::def_path::LocateBy;
+use crate::input::InputFormat;
+use crate::report::ReportFormat;
+use crate::retry::RetryStrategy;
+use crate::unpack_cache::parse_byte_size;
+
+/// 从 crates.io 源码中提取函数的文档注释与普通注释
+#[derive(Debug, Parser)]
+#[command(name = "extract_comment", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// 按函数记录文件（CSV 或 JSONL）逐条提取注释
+    Extract(ExtractArgs),
+    /// 扫描 crate 列表中每个 crate 的全部函数
+    Scan(ScanArgs),
+    /// 提取本地 .rs 文件或项目目录中全部函数的注释，不需要 crate 缓存或函数 CSV
+    Local(LocalArgs),
+    /// 汇总结果目录中的失败记录
+    Report(ReportArgs),
+    /// 重新处理 records_failed_to_extract.csv 中的失败记录
+    Retry(RetryArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ExtractArgs {
+    /// 函数记录文件，CSV 或 JSON Lines
+    #[arg(long, value_name = "FILE")]
+    pub functions: PathBuf,
+    /// 函数记录文件的格式，默认按扩展名判断（.jsonl/.ndjson 为 JSONL）
+    #[arg(long, value_name = "FORMAT")]
+    pub input_format: Option<InputFormat>,
+    /// CSV 第一行是表头，按列名选取字段
+    #[arg(long)]
+    pub headers: bool,
+    /// 列映射文件（.toml 或 .json），字段值可以是列名或从 0 开始的列下标
+    #[arg(long, value_name = "FILE")]
+    pub column_map: Option<PathBuf>,
+    /// 路径改写规则文件（.toml 或 .json），把记录中的绝对路径改写为 crate 内相对路径；
+    /// 其中的规则先于内置的 `registry/src/<index>/<crate>/` 规则尝试
+    #[arg(long, value_name = "FILE")]
+    pub path_rewrite: Option<PathBuf>,
+    #[command(flatten)]
+    pub cache: CacheArgs,
+    /// 结果与失败记录的输出目录
+    #[arg(long, value_name = "DIR")]
+    pub result_dir: PathBuf,
+    /// 只处理 safety 等于这些值的记录，可重复指定；与 --filter 都未指定时默认为 Safe
+    #[arg(long = "safety", value_name = "SAFETY")]
+    pub safety: Vec<String>,
+    /// 过滤表达式，例如 `safety in [Unsafe, Safe] && crate =~ "^tokio"`
+    #[arg(long, value_name = "EXPR")]
+    pub filter: Option<String>,
+    /// 跳过输入中的前 N 条记录
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub start_offset: usize,
+    /// 读取结果目录中的进度日志，跳过上次已经完成的记录
+    #[arg(long)]
+    pub resume: bool,
+    /// 定位函数的方式：def-path 先按 def_path 在 crate 模块树中查找，找不到或有歧义时按行号；line 只按行号
+    #[arg(long, value_name = "MODE", value_enum, default_value_t = LocateBy::DefPath)]
+    pub locate_by: LocateBy,
+}
+
+/// crate 源码缓存的位置与结构，extract、scan 与 retry 共用
+#[derive(Debug, Args)]
+pub struct CacheArgs {
+    /// crate 缓存根目录：<cache_root>/<crate_name>/<name-version>.crate，或者直接是 CARGO_HOME
+    #[arg(long, value_name = "DIR")]
+    pub cache_root: PathBuf,
+    /// 缓存结构，auto 时发现 registry/cache 或 registry/src 即按 cargo-home 处理
+    #[arg(long, value_name = "LAYOUT", value_enum, default_value_t = CacheLayout::Auto)]
+    pub cache_layout: CacheLayout,
+    /// crate 别名文件（.toml 或 .json），内容是 `记录中的名字 = "缓存中的包名"`，用于改过名的包
+    #[arg(long, value_name = "FILE")]
+    pub crate_aliases: Option<PathBuf>,
+    /// 本地 crates.io index（index 仓库的 checkout 或 JSON Lines 文件）；指定时用其中的 cksum 校验每个 .crate 的 SHA-256，
+    /// 不一致的记录写入失败文件
+    #[arg(long, value_name = "PATH")]
+    pub crate_index: Option<PathBuf>,
+    /// git 依赖的 checkout 目录（cargo-home/git/checkouts）；cargo-home 结构下默认使用其中的 git/checkouts
+    #[arg(long, value_name = "DIR")]
+    pub git_checkouts: Option<PathBuf>,
+    /// 以 PREFIX 开头的记录路径直接从本地目录 DIR 读取，可重复指定，按顺序匹配
+    #[arg(long = "source-dir", value_name = "PREFIX=DIR")]
+    pub source_dirs: Vec<String>,
+    /// 把 .crate 归档解压到磁盘后再读取；默认直接从归档把 .rs 文件读入内存
+    #[arg(long)]
+    pub unpack_to_disk: bool,
+    /// 归档中单个条目的大小上限，超过的条目被跳过并记录到 unpack_rejections.jsonl
+    #[arg(long, value_name = "BYTES", value_parser = parse_byte_size, default_value = "64M")]
+    pub max_entry_size: u64,
+    /// 一个 crate 归档解压后的总大小上限，超过时放弃整个 crate
+    #[arg(long, value_name = "BYTES", value_parser = parse_byte_size, default_value = "1G")]
+    pub max_crate_size: u64,
+    /// --unpack-to-disk 时解压目录的总大小上限，例如 500M、20G；超出时删除最久未使用的目录，默认不限制
+    #[arg(long, value_name = "BYTES", value_parser = parse_byte_size)]
+    pub unpack_budget: Option<u64>,
+    /// --unpack-to-disk 时的解压目录，其中的目录跨运行复用；默认 legacy 结构解压到 crate 自己的目录，cargo-home 结构解压到系统临时目录
+    #[arg(long, value_name = "DIR")]
+    pub unpack_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ScanArgs {
+    /// crate 列表 JSON 文件
+    #[arg(long, value_name = "JSON")]
+    pub crate_list: PathBuf,
+    #[command(flatten)]
+    pub cache: CacheArgs,
+    /// 结果输出目录
+    #[arg(long, value_name = "DIR")]
+    pub result_dir: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct LocalArgs {
+    /// 单个 .rs 文件或项目目录
+    #[arg(value_name = "PATH")]
+    pub path: PathBuf,
+    /// 输出中使用的 crate 名，默认取 Cargo.toml 中的包名，其次是去掉版本后缀的目录名或文件名
+    #[arg(long, value_name = "NAME")]
+    pub crate_name: Option<String>,
+    /// 结果输出目录；不指定时把 JSON 打印到标准输出
+    #[arg(long, value_name = "DIR")]
+    pub result_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    /// 之前运行 extract、scan 或 retry 时使用的结果目录，从中读取 failures.jsonl
+    #[arg(long, value_name = "DIR")]
+    pub result_dir: PathBuf,
+    /// 输出格式
+    #[arg(long, value_name = "FORMAT", value_enum, default_value_t = ReportFormat::Text)]
+    pub format: ReportFormat,
+    /// 每组列出的示例记录数
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub examples: usize,
+    /// 每种分组只保留数量最多的 N 组，0 表示全部保留
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    pub top: usize,
+    /// 报告写入的文件；不指定时打印到标准输出
+    #[arg(long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct RetryArgs {
+    #[command(flatten)]
+    pub cache: CacheArgs,
+    /// 之前运行 extract 时使用的结果目录，从中读取 records_failed_to_extract.csv（或 .jsonl）
+    #[arg(long, value_name = "DIR")]
+    pub result_dir: PathBuf,
+    /// 失败记录 CSV 第一行是表头，extract 时使用了 --headers 时需要指定
+    #[arg(long)]
+    pub headers: bool,
+    /// extract 时使用的列映射文件
+    #[arg(long, value_name = "FILE")]
+    pub column_map: Option<PathBuf>,
+    /// extract 时使用的路径改写规则文件
+    #[arg(long, value_name = "FILE")]
+    pub path_rewrite: Option<PathBuf>,
+    /// 备选的路径改写规则文件，可重复指定；按 --path-rewrite 改写出的文件不存在时依次尝试
+    #[arg(long = "alt-path-rewrite", value_name = "FILE")]
+    pub alt_path_rewrites: Vec<PathBuf>,
+    /// 启用的补救策略，可重复指定，默认全部启用
+    #[arg(long = "strategy", value_name = "STRATEGY", value_enum)]
+    pub strategies: Vec<RetryStrategy>,
+    /// fuzzy-line 策略在起始行上下查找函数的行数
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub line_window: usize,
+    /// 定位函数的方式，与 extract 的 --locate-by 相同
+    #[arg(long, value_name = "MODE", value_enum, default_value_t = LocateBy::DefPath)]
+    pub locate_by: LocateBy,
+    /// 恢复出的结果与仍然失败的记录的输出目录，默认为 <result-dir>/retry
+    #[arg(long, value_name = "DIR")]
+    pub output_dir: Option<PathBuf>,
+}
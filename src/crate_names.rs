@@ -0,0 +1,75 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+/// crate 名比较用的键：不区分大小写，也不区分 `-` 与 `_`
+pub fn name_key(crate_name: &str) -> String {
+    crate_name.to_ascii_lowercase().replace('_', "-")
+}
+
+/// 缓存中所有 crate 名的索引，启动时扫描一次 cache_root 建立，之后的查找都经过这里
+#[derive(Debug, Default)]
+pub struct CrateNameIndex {
+    /// 名字键 -> 缓存中实际出现的名字
+    names: HashMap<String, BTreeSet<String>>,
+    /// 别名的名字键 -> 真实的包名，用于改过名的包
+    aliases: HashMap<String, String>,
+}
+
+impl CrateNameIndex {
+    pub fn new(names: impl IntoIterator<Item = String>) -> CrateNameIndex {
+        let mut index = CrateNameIndex::default();
+        for name in names {
+            index.names.entry(name_key(&name)).or_default().insert(name);
+        }
+        index
+    }
+
+    /// 读取别名文件（.toml 或 .json），内容是 `记录中的名字 = "缓存中的包名"`
+    pub fn load_aliases(&mut self, aliases_path: &Path) -> Result<()> {
+        let text = fs::read_to_string(aliases_path)
+            .with_context(|| format!("cannot read crate aliases {:?}", aliases_path))?;
+        let aliases: HashMap<String, String> = if aliases_path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&text).with_context(|| format!("invalid crate aliases {:?}", aliases_path))?
+        } else {
+            toml::from_str(&text).with_context(|| format!("invalid crate aliases {:?}", aliases_path))?
+        };
+        for (alias, target) in aliases {
+            self.aliases.insert(name_key(&alias), target);
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.values().map(BTreeSet::len).sum()
+    }
+
+    /// 把记录中的 crate 名解析成缓存中实际使用的名字
+    ///
+    /// 先查别名，再找完全相同的名字，最后按名字键匹配；按名字键匹配到多个名字时报告歧义。
+    pub fn resolve(&self, crate_name: &str) -> Result<String> {
+        let wanted = match self.aliases.get(&name_key(crate_name)) {
+            Some(target) => target.as_str(),
+            None => crate_name,
+        };
+        let Some(candidates) = self.names.get(&name_key(wanted)) else {
+            if wanted != crate_name {
+                bail!("crate {} (alias of {}) is not in the crate cache", wanted, crate_name);
+            }
+            bail!("crate {} is not in the crate cache", crate_name);
+        };
+        if candidates.contains(wanted) {
+            return Ok(wanted.to_owned());
+        }
+        match candidates.len() {
+            1 => Ok(candidates.iter().next().expect("one candidate").clone()),
+            _ => bail!(
+                "crate name {} is ambiguous, the crate cache has [{}]",
+                crate_name,
+                candidates.iter().cloned().collect::<Vec<_>>().join(", ")
+            ),
+        }
+    }
+}
@@ -0,0 +1,604 @@
+use std::cmp::Ordering;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use crate::checksum::ChecksumIndex;
+use crate::cli::CacheArgs;
+use crate::crate_names::{name_key, CrateNameIndex};
+use crate::failure::{Failure, FailureKind, FailureLocation};
+use crate::safe_unpack::{unpack_crate, UnpackGuard, UnpackLimits, UnpackRejection};
+use crate::unpack_cache::UnpackCache;
+use crate::vfs::CrateFiles;
+
+/// crate 缓存目录的结构
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CacheLayout {
+    /// 存在 registry/cache 或 registry/src 时按 cargo-home 处理，否则按 legacy 处理
+    Auto,
+    /// <cache_root>/<crate_name>/<name>-<version>.crate
+    Legacy,
+    /// cargo 自己的 registry/cache/<index>/<name>-<version>.crate 与 registry/src/<index>/<name>-<version>/
+    CargoHome,
+}
+
+/// 一个 crate 的源码所在位置
+#[derive(Debug, Clone)]
+pub enum CrateLocation {
+    /// 需要解压的 .crate 归档，解压到 unpack_dir 下；version 是查找时确定的版本，校验 cksum 时使用
+    Archive {
+        archive: PathBuf,
+        version: String,
+        unpack_dir: PathBuf,
+    },
+    /// 已经解压好的源码目录（cargo 的 registry/src、git checkout 或本地目录），只读使用，用完不删除
+    Extracted(PathBuf),
+}
+
+/// 查找结果：缓存中实际使用的 crate 名（可能与记录中的名字大小写、`-`/`_` 不同或是别名）以及源码位置
+#[derive(Debug, Clone)]
+pub struct LocatedCrate {
+    pub crate_name: String,
+    pub location: CrateLocation,
+}
+
+/// 由 git checkout 或本地目录提供者认领的记录路径：源码根目录与其中的相对路径
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceRoute {
+    pub root: PathBuf,
+    pub rel_file: String,
+}
+
+/// 已经可以按相对路径读取的 crate 源码
+#[derive(Debug)]
+pub struct OpenedCrate {
+    pub files: CrateFiles,
+    /// 指定 --crate-index 时校验过的 .crate SHA-256
+    pub checksum: Option<String>,
+    /// 指定了 --crate-index，但源码来自已解压的目录，没有可校验的 .crate
+    pub checksum_unverifiable: bool,
+}
+
+impl OpenedCrate {
+    /// 无法校验时写入失败文件的失败，location 是 crate 的位置；这样指定 --crate-index 时，
+    /// 没有出现在失败文件中的 crate 都通过了校验
+    pub fn unverifiable_failure(&self, location: &FailureLocation) -> Option<Failure> {
+        self.checksum_unverifiable.then(|| {
+            Failure::new(
+                FailureKind::ChecksumUnverifiable,
+                format!("cannot verify checksum of extracted source {:?}", self.files.root()),
+            )
+            .at(location)
+        })
+    }
+}
+
+#[derive(Debug)]
+enum ResolvedLayout {
+    Legacy,
+    CargoHome {
+        registry: PathBuf,
+        /// registry/src 下已解压的 `<name>-<version>` 目录，启动时扫描一次
+        extracted: Vec<PathBuf>,
+        /// registry/cache 下的 `<name>-<version>.crate`，启动时扫描一次
+        archives: Vec<PathBuf>,
+    },
+}
+
+/// 按缓存结构查找、解压与清理 crate 源码
+#[derive(Debug)]
+pub struct CrateCache {
+    root: PathBuf,
+    layout: ResolvedLayout,
+    /// 缓存中所有 crate 名的索引
+    names: CrateNameIndex,
+    unpack_dir: Option<PathBuf>,
+    /// 归档条目的安全检查，记录被拒绝的条目
+    guard: UnpackGuard,
+    /// 指定 --crate-index 时用来校验 .crate 的 cksum
+    checksums: Option<ChecksumIndex>,
+    /// `--unpack-to-disk` 时解压出的目录，跨运行保留并按预算淘汰
+    unpacked: Option<UnpackCache>,
+    /// 本地的 cargo-home/git/checkouts 目录
+    git_checkouts: Option<PathBuf>,
+    /// 记录路径前缀 -> 本地目录
+    source_dirs: Vec<(PathBuf, PathBuf)>,
+}
+
+impl CrateCache {
+    pub fn new(args: &CacheArgs) -> Result<CrateCache> {
+        let root = args.cache_root.clone();
+        if !root.is_dir() {
+            bail!("crate cache root {:?} is not a directory", root);
+        }
+        // 既可以指向 CARGO_HOME，也可以直接指向其中的 registry 目录
+        let registry = if root.join("registry").is_dir() {
+            root.join("registry")
+        } else {
+            root.clone()
+        };
+        let looks_like_cargo_home = registry.join("cache").is_dir() || registry.join("src").is_dir();
+        let use_cargo_home = match args.cache_layout {
+            CacheLayout::Legacy => false,
+            CacheLayout::CargoHome if !looks_like_cargo_home => {
+                bail!("{:?} has no registry/cache or registry/src directory", root)
+            }
+            CacheLayout::CargoHome => true,
+            CacheLayout::Auto => looks_like_cargo_home,
+        };
+        // 只扫描一次缓存目录，之后的查找都使用这里建立的索引
+        let (layout, mut names) = if use_cargo_home {
+            let extracted = registry_entries(&registry.join("src"), |path| path.is_dir());
+            let archives = registry_entries(&registry.join("cache"), |path| {
+                path.extension().is_some_and(|ext| ext == "crate")
+            });
+            let names = registry_name_index(extracted.iter().chain(&archives));
+            println!(
+                "crate cache {:?} uses cargo-home layout: {} extracted crates, {} archives",
+                root,
+                extracted.len(),
+                archives.len()
+            );
+            (
+                ResolvedLayout::CargoHome {
+                    registry,
+                    extracted,
+                    archives,
+                },
+                names,
+            )
+        } else {
+            let crate_dirs = fs::read_dir(&root)
+                .with_context(|| format!("cannot read crate cache root {:?}", root))?
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_dir())
+                .filter_map(|entry| entry.file_name().to_str().map(str::to_owned));
+            let names = CrateNameIndex::new(crate_dirs);
+            println!("crate cache {:?} uses legacy layout: {} crate directories", root, names.len());
+            (ResolvedLayout::Legacy, names)
+        };
+        if let Some(aliases_path) = &args.crate_aliases {
+            names.load_aliases(aliases_path)?;
+        }
+        // cargo-home 结构下默认使用同一个 CARGO_HOME 中的 git checkout
+        let git_checkouts = match (&args.git_checkouts, &layout) {
+            (Some(git_checkouts), _) => Some(git_checkouts.clone()),
+            (None, ResolvedLayout::CargoHome { registry, .. }) => registry
+                .parent()
+                .map(|cargo_home| cargo_home.join("git").join("checkouts"))
+                .filter(|git_checkouts| git_checkouts.is_dir()),
+            (None, ResolvedLayout::Legacy) => None,
+        };
+        let source_dirs = args
+            .source_dirs
+            .iter()
+            .map(|mapping| match mapping.split_once('=') {
+                Some((prefix, dir)) if !prefix.is_empty() && !dir.is_empty() => Ok((PathBuf::from(prefix), PathBuf::from(dir))),
+                _ => bail!("invalid --source-dir {:?}, expected PREFIX=DIR", mapping),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let unpacked = if args.unpack_to_disk {
+            let index_dir = match (&args.unpack_dir, &layout) {
+                (Some(unpack_dir), _) => unpack_dir.clone(),
+                (None, ResolvedLayout::Legacy) => root.clone(),
+                (None, ResolvedLayout::CargoHome { .. }) => default_unpack_dir(),
+            };
+            fs::create_dir_all(&index_dir)
+                .with_context(|| format!("cannot create unpack directory {:?}", index_dir))?;
+            Some(UnpackCache::open(&index_dir, args.unpack_budget)?)
+        } else {
+            None
+        };
+        Ok(CrateCache {
+            root,
+            layout,
+            names,
+            unpack_dir: args.unpack_dir.clone(),
+            guard: UnpackGuard::new(UnpackLimits {
+                max_entry_bytes: args.max_entry_size,
+                max_total_bytes: args.max_crate_size,
+            }),
+            checksums: args.crate_index.as_deref().map(ChecksumIndex::open).transpose()?,
+            unpacked,
+            git_checkouts,
+            source_dirs,
+        })
+    }
+
+    /// 找出认领记录路径的源码提供者：先按 --source-dir 的顺序匹配前缀，再识别 git/checkouts 路径；
+    /// 都不认领时返回 None，记录交给 .crate 提供者
+    pub fn route(&self, file: &str) -> Option<SourceRoute> {
+        let file = PathBuf::from(file.replace('\\', "/"));
+        for (prefix, dir) in &self.source_dirs {
+            if let Ok(rest) = file.strip_prefix(prefix) {
+                return Some(SourceRoute {
+                    root: dir.clone(),
+                    rel_file: rest.to_string_lossy().into_owned(),
+                });
+            }
+        }
+        let git_checkouts = self.git_checkouts.as_ref()?;
+        // .../git/checkouts/<repo>-<hash>/<rev>/<crate 内路径>
+        let parts: Vec<_> = file.iter().collect();
+        let checkouts_index = parts.windows(2).position(|pair| pair[0] == "git" && pair[1] == "checkouts")?;
+        let (repo, rev) = (parts.get(checkouts_index + 2)?, parts.get(checkouts_index + 3)?);
+        let rel_file: PathBuf = parts[checkouts_index + 4..].iter().collect();
+        Some(SourceRoute {
+            root: git_checkouts.join(repo).join(rev),
+            rel_file: rel_file.to_string_lossy().into_owned(),
+        })
+    }
+
+    /// 把记录中的 crate 名解析成缓存中实际使用的名字，见 [`CrateNameIndex::resolve`]
+    pub fn resolve_name(&self, crate_name: &str) -> Result<String> {
+        self.names.resolve(crate_name)
+    }
+
+    /// 查找 crate 源码；crate 名先经过名字索引解析，指定版本时只接受完全匹配的 `<name>-<version>`
+    pub fn locate(&self, crate_name: &str, version: Option<&str>) -> Result<LocatedCrate> {
+        let crate_name = self.names.resolve(crate_name)?;
+        match &self.layout {
+            ResolvedLayout::Legacy => {
+                let target_crate_path = self.root.join(&crate_name);
+                let (archive, version) = find_crate_archive(&target_crate_path, &crate_name, version)?;
+                Ok(LocatedCrate {
+                    crate_name,
+                    location: CrateLocation::Archive {
+                        archive,
+                        version,
+                        unpack_dir: self.unpack_dir.clone().unwrap_or(target_crate_path),
+                    },
+                })
+            }
+            ResolvedLayout::CargoHome {
+                registry,
+                extracted,
+                archives,
+            } => {
+                // 优先使用 cargo 已经解压好的源码，其次才解压 registry/cache 中的归档；
+                // 需要校验 cksum 时反过来，只有归档可以校验
+                let found_extracted = || {
+                    pick_versioned(extracted, &crate_name, version).map(|(path, _)| CrateLocation::Extracted(path.clone()))
+                };
+                let found_archive = || {
+                    pick_versioned(archives, &crate_name, version).map(|(archive, version)| CrateLocation::Archive {
+                        archive: archive.clone(),
+                        version: version.to_owned(),
+                        unpack_dir: self.unpack_dir.clone().unwrap_or_else(default_unpack_dir),
+                    })
+                };
+                let found = if self.checksums.is_some() {
+                    found_archive().or_else(found_extracted)
+                } else {
+                    found_extracted().or_else(found_archive)
+                };
+                match (found, version) {
+                    (Some(location), _) => Ok(LocatedCrate { crate_name, location }),
+                    (None, Some(version)) => bail!(
+                        "crate {} version {} not found in registry {:?}",
+                        crate_name,
+                        version,
+                        registry
+                    ),
+                    (None, None) => bail!("cannot find any crate named {} in registry {:?}", crate_name, registry),
+                }
+            }
+        }
+    }
+
+    /// 让 crate 源码可以按相对路径访问；归档默认直接读入内存，`--unpack-to-disk` 时才解压，
+    /// 已经解压过的目录直接复用
+    pub fn open(&mut self, located: &LocatedCrate) -> Result<OpenedCrate> {
+        let checksum = match (&located.location, &mut self.checksums) {
+            (CrateLocation::Archive { archive, version, .. }, Some(checksums)) => {
+                let checksum = checksums.verify(archive, &located.crate_name, version)?;
+                println!("verified checksum of {:?}: {}", archive, checksum);
+                Some(checksum)
+            }
+            (CrateLocation::Extracted(path), Some(_)) => {
+                println!("cannot verify checksum of extracted source {:?}", path);
+                None
+            }
+            (_, None) => None,
+        };
+        let checksum_unverifiable = self.checksums.is_some() && checksum.is_none();
+        let guard = &mut self.guard;
+        let files = match (&located.location, &mut self.unpacked) {
+            (CrateLocation::Extracted(path), _) if !path.is_dir() => bail!("source directory {:?} does not exist", path),
+            (CrateLocation::Extracted(path), _) => CrateFiles::Dir(path.clone()),
+            (CrateLocation::Archive { archive, .. }, None) => CrateFiles::load_tarball(&mut self.guard, archive)?,
+            (CrateLocation::Archive { archive, unpack_dir, .. }, Some(unpacked)) => {
+                fs::create_dir_all(unpack_dir)
+                    .with_context(|| format!("cannot create unpack directory {:?}", unpack_dir))?;
+                let stem = archive
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .with_context(|| format!("invalid crate archive name {:?}", archive))?;
+                let crate_dir = unpack_dir.join(stem);
+                let root = unpacked.get_or_unpack(&crate_dir, || {
+                    // 先解压到临时目录再改名，中断的解压不会被当成完整目录复用
+                    let staging = unpack_dir.join(format!(".partial-{}", stem));
+                    let _ = fs::remove_dir_all(&staging);
+                    let staged_dir = unpack_crate(guard, &staging, archive)?;
+                    fs::rename(&staged_dir, &crate_dir)
+                        .with_context(|| format!("cannot move {:?} to {:?}", staged_dir, crate_dir))?;
+                    let _ = fs::remove_dir_all(&staging);
+                    Ok(())
+                })?;
+                CrateFiles::Dir(root)
+            }
+        };
+        Ok(OpenedCrate {
+            files,
+            checksum,
+            checksum_unverifiable,
+        })
+    }
+
+    /// 取出打开 crate 时被拒绝的归档条目，无论打开是否成功都应调用
+    pub fn take_rejections(&mut self) -> Vec<UnpackRejection> {
+        self.guard.take_rejections()
+    }
+}
+
+/// cargo-home 结构下未指定 --unpack-dir 时的解压目录
+fn default_unpack_dir() -> PathBuf {
+    std::env::temp_dir().join("extract_comment_unpacked")
+}
+
+/// registry/src 或 registry/cache 下所有 index 目录中满足条件的条目，按路径排序
+fn registry_entries(dir: &Path, keep: impl Fn(&Path) -> bool) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|index_dir| index_dir.ok())
+        .map(|index_dir| index_dir.path())
+        .filter(|index_dir| index_dir.is_dir())
+        .flat_map(|index_dir| fs::read_dir(index_dir).into_iter().flatten())
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| keep(path))
+        .collect();
+    entries.sort();
+    entries
+}
+
+/// registry/src 与 registry/cache 条目中所有 crate 名的索引
+fn registry_name_index<'a>(entries: impl IntoIterator<Item = &'a PathBuf>) -> CrateNameIndex {
+    CrateNameIndex::new(
+        entries
+            .into_iter()
+            .filter_map(|path| entry_name_version(path))
+            .map(|(name, _)| name.to_owned())
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// 在 `<name>-<version>` 形式的条目中挑出名字完全相同的 crate 及其版本；未指定版本时取 semver 最高的
+fn pick_versioned<'a>(entries: &'a [PathBuf], crate_name: &str, version: Option<&str>) -> Option<(&'a PathBuf, &'a str)> {
+    let mut candidates = entries.iter().filter_map(|path| {
+        let (name, entry_version) = entry_name_version(path)?;
+        (name == crate_name).then_some((path, entry_version))
+    });
+    match version {
+        Some(version) => candidates.find(|(_, entry_version)| *entry_version == version),
+        None => candidates.max_by(|(_, a), (_, b)| compare_semver(a, b)),
+    }
+}
+
+/// registry/src 目录名或 registry/cache 归档名中的 crate 名与版本
+fn entry_name_version(path: &Path) -> Option<(&str, &str)> {
+    let stem = if path.extension().is_some_and(|ext| ext == "crate") {
+        path.file_stem()
+    } else {
+        path.file_name()
+    }?;
+    split_name_version(stem.to_str()?)
+}
+
+/// 把 `serde_json-1.0.1` 拆成 (`serde_json`, `1.0.1`)
+///
+/// crate 名本身可以含有 `-数字`（如 `md-5`），版本也可以含有 `-`（如 `1.0.0-alpha.1`），
+/// 所以从右往左取第一个前面是合法 crate 名、后面是 semver 版本的 `-`。
+pub fn split_name_version(stem: &str) -> Option<(&str, &str)> {
+    stem.rmatch_indices('-')
+        .map(|(index, _)| (&stem[..index], &stem[index + 1..]))
+        .find(|(name, version)| is_crate_name(name) && is_semver(version))
+}
+
+fn is_crate_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// `MAJOR.MINOR.PATCH`，可带 `-pre.release` 与 `+build` 后缀
+fn is_semver(version: &str) -> bool {
+    let (version, build) = match version.split_once('+') {
+        Some((version, build)) => (version, Some(build)),
+        None => (version, None),
+    };
+    let (core, pre_release) = match version.split_once('-') {
+        Some((core, pre_release)) => (core, Some(pre_release)),
+        None => (version, None),
+    };
+    let identifiers_valid = |identifiers: &str| {
+        identifiers
+            .split('.')
+            .all(|identifier| !identifier.is_empty() && identifier.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+    };
+    let core_parts: Vec<&str> = core.split('.').collect();
+    core_parts.len() == 3
+        && core_parts.iter().all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+        && pre_release.is_none_or(identifiers_valid)
+        && build.is_none_or(identifiers_valid)
+}
+
+/// 按 semver 的优先级比较两个通过 is_semver 检查的版本：先比较数字部分，
+/// 同一版本的预发布版低于正式版，预发布标识逐段比较，build 后缀不参与比较
+fn compare_semver(a: &str, b: &str) -> Ordering {
+    fn split(version: &str) -> (Vec<u64>, Option<&str>) {
+        let version = version.split_once('+').map_or(version, |(version, _)| version);
+        let (core, pre_release) = match version.split_once('-') {
+            Some((core, pre_release)) => (core, Some(pre_release)),
+            None => (version, None),
+        };
+        (core.split('.').map(|part| part.parse().unwrap_or(u64::MAX)).collect(), pre_release)
+    }
+    let (a_core, a_pre_release) = split(a);
+    let (b_core, b_pre_release) = split(b);
+    a_core.cmp(&b_core).then_with(|| match (a_pre_release, b_pre_release) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => {
+            let mut a_identifiers = a.split('.');
+            let mut b_identifiers = b.split('.');
+            loop {
+                let ordering = match (a_identifiers.next(), b_identifiers.next()) {
+                    (None, None) => return Ordering::Equal,
+                    (None, Some(_)) => return Ordering::Less,
+                    (Some(_), None) => return Ordering::Greater,
+                    // 纯数字的标识按数值比较，且低于含字母的标识
+                    (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                        (Ok(a), Ok(b)) => a.cmp(&b),
+                        (Ok(_), Err(_)) => Ordering::Less,
+                        (Err(_), Ok(_)) => Ordering::Greater,
+                        (Err(_), Err(_)) => a.cmp(b),
+                    },
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+        }
+    })
+}
+
+/// 在 crate 目录中选出要解压的 .crate 文件及其版本
+///
+/// 指定版本时只接受 `<name>-<version>.crate`，找不到时在错误中列出目录里已有的归档；
+/// 未指定版本时使用版本最高的 .crate，版本取文件名中 crate 名之后的部分。
+fn find_crate_archive(target_crate_path: &Path, crate_name: &str, version: Option<&str>) -> Result<(PathBuf, String)> {
+    let entries = fs::read_dir(target_crate_path)
+        .with_context(|| format!("cannot read dir {:?}", target_crate_path))?;
+    let mut archives = Vec::new();
+    for entry_res in entries {
+        let entry = match entry_res {
+            Ok(en) => en,
+            Err(e) => {
+                println!("cannot read item in {:?} error: {}", target_crate_path, e);
+                continue;
+            }
+        };
+        let item_path = entry.path();
+        if item_path.extension().and_then(|e| e.to_str()).is_some_and(|ext| ext.eq_ignore_ascii_case("crate")) {
+            archives.push(item_path);
+        }
+    }
+    archives.sort();
+    let archive_stem = |path: &PathBuf| path.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_owned();
+    match version {
+        Some(version) => {
+            let wanted = name_key(&format!("{}-{}", crate_name, version));
+            match archives.iter().find(|path| name_key(&archive_stem(path)) == wanted) {
+                Some(path) => Ok((path.clone(), version.to_owned())),
+                None => bail!(
+                    "crate {} version {} not found in {:?}, available: [{}]",
+                    crate_name,
+                    version,
+                    target_crate_path,
+                    archives.iter().map(archive_stem).collect::<Vec<_>>().join(", ")
+                ),
+            }
+        }
+        None => {
+            if archives.is_empty() {
+                bail!("cannot find any crate in {:?}", target_crate_path);
+            }
+            // 目录名已按名字键与 crate 名匹配，归档名的前缀只可能在大小写与 `-`/`_` 上不同
+            let archive_version = |archive: &PathBuf| {
+                let stem = archive_stem(archive);
+                let version = stem
+                    .get(crate_name.len() + 1..)
+                    .filter(|version| is_semver(version))
+                    .filter(|_| stem.get(..crate_name.len()).is_some_and(|prefix| name_key(prefix) == name_key(crate_name)))?
+                    .to_owned();
+                Some((archive.clone(), version))
+            };
+            let (archive, version) = archives
+                .iter()
+                .filter_map(archive_version)
+                .max_by(|(_, a), (_, b)| compare_semver(a, b))
+                .with_context(|| format!("cannot read the version of {} from any archive name in {:?}", crate_name, target_crate_path))?;
+            if archives.len() > 1 {
+                println!("no version given for {}, using {:?} out of {} archives", crate_name, archive, archives.len());
+            }
+            Ok((archive, version))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_name_version_keeps_hyphen_digit_names() {
+        assert_eq!(split_name_version("serde_json-1.0.1"), Some(("serde_json", "1.0.1")));
+        assert_eq!(split_name_version("md-5-0.10.6"), Some(("md-5", "0.10.6")));
+        assert_eq!(split_name_version("sha-1-0.10.1"), Some(("sha-1", "0.10.1")));
+        assert_eq!(split_name_version("foo-bar-1.0.0-alpha.1"), Some(("foo-bar", "1.0.0-alpha.1")));
+        assert_eq!(split_name_version("base-x-0.2.11+build.5"), Some(("base-x", "0.2.11+build.5")));
+        assert_eq!(split_name_version("no-version"), None);
+        assert_eq!(split_name_version("md-5"), None);
+    }
+
+    #[test]
+    fn registry_name_index_keeps_hyphen_digit_names() {
+        let entries = [
+            PathBuf::from("registry/cache/index.crates.io-abc/md-5-0.10.6.crate"),
+            PathBuf::from("registry/src/index.crates.io-abc/sha-1-0.10.1"),
+            PathBuf::from("registry/src/index.crates.io-abc/serde_json-1.0.1"),
+        ];
+        let names = registry_name_index(&entries);
+        assert_eq!(names.len(), 3);
+        assert_eq!(names.resolve("md-5").unwrap(), "md-5");
+        assert_eq!(names.resolve("md_5").unwrap(), "md-5");
+        assert_eq!(names.resolve("sha-1").unwrap(), "sha-1");
+        assert_eq!(names.resolve("serde-json").unwrap(), "serde_json");
+        assert!(names.resolve("md").is_err());
+        assert_eq!(pick_versioned(&entries, "md-5", Some("0.10.6")), Some((&entries[0], "0.10.6")));
+    }
+
+    #[test]
+    fn compare_semver_follows_precedence() {
+        let mut versions = ["1.10.0", "1.9.0", "1.10.0-rc.1", "1.10.0-alpha", "1.10.0-rc.10", "1.10.0-rc.2", "0.99.99", "1.10.0-alpha.beta"];
+        versions.sort_by(|a, b| compare_semver(a, b));
+        assert_eq!(versions, ["0.99.99", "1.9.0", "1.10.0-alpha", "1.10.0-alpha.beta", "1.10.0-rc.1", "1.10.0-rc.2", "1.10.0-rc.10", "1.10.0"]);
+        assert_eq!(compare_semver("1.0.0+build.1", "1.0.0"), Ordering::Equal);
+    }
+
+    #[test]
+    fn unversioned_records_use_the_highest_version() {
+        // 按文件名排序时 1.10.0 排在 1.9.0 前面，foo-bar 不是 foo
+        let entries = [
+            PathBuf::from("registry/src/index.crates.io-abc/foo-1.10.0"),
+            PathBuf::from("registry/src/index.crates.io-abc/foo-1.10.0-rc.1"),
+            PathBuf::from("registry/src/index.crates.io-abc/foo-1.9.0"),
+            PathBuf::from("registry/src/index.crates.io-abc/foo-bar-9.0.0"),
+        ];
+        assert_eq!(pick_versioned(&entries, "foo", None), Some((&entries[0], "1.10.0")));
+        assert_eq!(pick_versioned(&entries[1..], "foo", None), Some((&entries[1], "1.10.0-rc.1")));
+        assert_eq!(pick_versioned(&entries, "foo", Some("1.9.0")), Some((&entries[2], "1.9.0")));
+
+        let crate_dir = std::env::temp_dir().join(format!("crate_source_foo_{}", std::process::id()));
+        fs::create_dir_all(&crate_dir).unwrap();
+        for version in ["1.9.0", "1.10.0", "1.10.0-rc.1"] {
+            fs::write(crate_dir.join(format!("foo-{}.crate", version)), b"").unwrap();
+        }
+        let (archive, version) = find_crate_archive(&crate_dir, "foo", None).unwrap();
+        assert_eq!((archive, version.as_str()), (crate_dir.join("foo-1.10.0.crate"), "1.10.0"));
+        assert!(find_crate_archive(&crate_dir, "foo", Some("2.0.0")).is_err());
+        fs::remove_dir_all(&crate_dir).unwrap();
+    }
+}
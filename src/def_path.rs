@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use quote::ToTokens;
+use syn::ext::IdentExt;
+use syn::spanned::Spanned;
+use syn::{Ident, ImplItem, Item, TraitItem};
+
+use crate::ast_cache::AstCache;
+use crate::module_tree::ModuleTree;
+use crate::vfs::CrateFiles;
+
+/// 按什么定位记录中的函数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LocateBy {
+    /// 先按 def_path 在模块树中查找，找不到或有歧义时按行号
+    DefPath,
+    /// 只按记录中的起始行号
+    Line,
+}
+
+/// 函数定义所在的文件与行范围
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionSite {
+    pub rel_file: PathBuf,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+impl FunctionSite {
+    pub fn contains(&self, rel_file: &Path, line: usize) -> bool {
+        self.rel_file == rel_file && self.start_line <= line && line <= self.end_line
+    }
+}
+
+/// 函数的直接上层
+enum Container {
+    /// 模块本身，extern 块不占路径
+    Module,
+    Impl {
+        self_ty: String,
+        trait_name: Option<String>,
+        /// 在所在模块的 impl 块中的序号，对应 rustc 的 `{impl#N}`
+        ordinal: usize,
+    },
+    Trait(String),
+}
+
+struct FunctionDef {
+    /// 所在模块的路径（不含 crate 名）
+    module_path: Vec<String>,
+    container: Container,
+    site: FunctionSite,
+}
+
+impl FunctionDef {
+    /// parents 是 def_path 中函数名之前、crate 名之后的各段；any_module 时不检查模块部分
+    fn matches(&self, parents: &[&str], any_module: bool) -> bool {
+        let modules = match &self.container {
+            Container::Module => parents,
+            Container::Trait(trait_name) => match parents.split_last() {
+                Some((segment, modules)) if segment == trait_name => modules,
+                _ => return false,
+            },
+            Container::Impl {
+                self_ty,
+                trait_name,
+                ordinal,
+            } => match parents.split_last() {
+                Some((segment, modules)) if impl_segment_matches(segment, self_ty, trait_name.as_deref(), *ordinal) => modules,
+                _ => return false,
+            },
+        };
+        any_module || modules.iter().copied().eq(self.module_path.iter().map(String::as_str))
+    }
+}
+
+/// crate 中所有函数按名字建立的索引，按 def_path 查找函数
+///
+/// 覆盖模块树中每个文件里的函数、宏，以及 impl、trait、extern 块中的函数。
+#[derive(Default)]
+pub struct DefPathIndex {
+    by_name: HashMap<String, Vec<FunctionDef>>,
+}
+
+impl DefPathIndex {
+    pub fn build(module_tree: &ModuleTree, crate_files: &CrateFiles, ast_cache: &mut AstCache) -> DefPathIndex {
+        let mut index = DefPathIndex::default();
+        for (rel_file, module_path) in module_tree.files() {
+            let Ok(parsed_source) = ast_cache.get_or_parse(crate_files, rel_file) else {
+                continue;
+            };
+            index.add_items(&parsed_source.parsed.file.items, rel_file, &mut module_path.to_vec());
+        }
+        index
+    }
+
+    fn add_items(&mut self, items: &[Item], rel_file: &Path, module_path: &mut Vec<String>) {
+        let mut impl_ordinal = 0;
+        for item in items {
+            match item {
+                Item::Fn(item_fn) => self.push(&item_fn.sig.ident, item_fn, Container::Module, rel_file, module_path),
+                Item::Macro(item_macro) => {
+                    if let Some(ident) = &item_macro.ident {
+                        self.push(ident, item_macro, Container::Module, rel_file, module_path);
+                    }
+                }
+                Item::Macro2(item_macro2) => self.push(&item_macro2.ident, item_macro2, Container::Module, rel_file, module_path),
+                Item::Mod(item_mod) => {
+                    if let Some((_, mod_items)) = &item_mod.content {
+                        module_path.push(item_mod.ident.unraw().to_string());
+                        self.add_items(mod_items, rel_file, module_path);
+                        module_path.pop();
+                    }
+                }
+                Item::Impl(item_impl) => {
+                    let self_ty = type_name(&item_impl.self_ty);
+                    let trait_name = item_impl
+                        .trait_
+                        .as_ref()
+                        .and_then(|(_, trait_path, _)| trait_path.segments.last())
+                        .map(|segment| segment.ident.unraw().to_string());
+                    for impl_item in &item_impl.items {
+                        if let ImplItem::Method(impl_item_method) = impl_item {
+                            let container = Container::Impl {
+                                self_ty: self_ty.clone(),
+                                trait_name: trait_name.clone(),
+                                ordinal: impl_ordinal,
+                            };
+                            self.push(&impl_item_method.sig.ident, impl_item_method, container, rel_file, module_path);
+                        }
+                    }
+                    impl_ordinal += 1;
+                }
+                Item::Trait(item_trait) => {
+                    let trait_name = item_trait.ident.unraw().to_string();
+                    for trait_item in &item_trait.items {
+                        if let TraitItem::Method(trait_item_method) = trait_item {
+                            let container = Container::Trait(trait_name.clone());
+                            self.push(&trait_item_method.sig.ident, trait_item_method, container, rel_file, module_path);
+                        }
+                    }
+                }
+                Item::ForeignMod(item_foreign_mod) => {
+                    for foreign_item in &item_foreign_mod.items {
+                        if let syn::ForeignItem::Fn(foreign_item_fn) = foreign_item {
+                            self.push(&foreign_item_fn.sig.ident, foreign_item_fn, Container::Module, rel_file, module_path);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn push(&mut self, ident: &Ident, node: &impl Spanned, container: Container, rel_file: &Path, module_path: &[String]) {
+        let span = node.span();
+        self.by_name.entry(ident.unraw().to_string()).or_default().push(FunctionDef {
+            module_path: module_path.to_vec(),
+            container,
+            site: FunctionSite {
+                rel_file: rel_file.to_path_buf(),
+                start_line: span.start().line,
+                end_line: span.end().line,
+            },
+        });
+    }
+
+    /// 查找 def_path 指向的函数，返回所有候选；有多个候选时由调用方按行号选择
+    ///
+    /// def_path 以 crate 名或 `crate` 开头，例如 `my_crate::a::Type::method`、`crate::a::{impl#0}::method`；
+    /// 也可以以 `<impl Trait for T>`、`<T as Trait>` 开头，此时不限定模块。
+    pub fn resolve(&self, def_path: &str, crate_name: &str) -> Vec<&FunctionSite> {
+        let segments = normalized_segments(def_path);
+        let Some((first, _)) = segments.split_first() else {
+            return Vec::new();
+        };
+        let (parents, any_module) = if *first == "crate" || *first == crate_name.replace('-', "_") {
+            (&segments[1..], false)
+        } else if first.starts_with('<') {
+            (&segments[..], true)
+        } else {
+            return Vec::new();
+        };
+        let Some((name, parents)) = parents.split_last() else {
+            return Vec::new();
+        };
+        self.by_name
+            .get(*name)
+            .into_iter()
+            .flatten()
+            .filter(|def| def.matches(parents, any_module))
+            .map(|def| &def.site)
+            .collect()
+    }
+}
+
+/// 按顶层的 `::` 切分 def_path，`<impl Trait for T>` 这样尖括号中的 `::` 不切分
+pub fn def_path_segments(def_path: &str) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    let bytes = def_path.as_bytes();
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'<' => depth += 1,
+            // `->` 中的 `>` 不是尖括号
+            b'>' if index == 0 || bytes[index - 1] != b'-' => depth = depth.saturating_sub(1),
+            b':' if depth == 0 && bytes.get(index + 1) == Some(&b':') => {
+                segments.push(&def_path[start..index]);
+                index += 2;
+                start = index;
+                continue;
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    segments.push(&def_path[start..]);
+    segments
+}
+
+/// 切分后去掉 `r#` 前缀与紧跟在名字后的泛型参数段（如 `Vec::<T>::new` 中的 `<T>`）
+fn normalized_segments(def_path: &str) -> Vec<&str> {
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in def_path_segments(def_path.trim()) {
+        let segment = segment.trim();
+        let generic_args = segment.starts_with('<')
+            && !is_qualified_segment(segment)
+            && segments.last().is_some_and(|previous| !previous.starts_with('<'));
+        if !generic_args {
+            segments.push(segment.strip_prefix("r#").unwrap_or(segment));
+        }
+    }
+    segments
+}
+
+/// `<impl Trait for T>`、`<impl T>` 或 `<T as Trait>`
+fn is_qualified_segment(segment: &str) -> bool {
+    // 与 impl_segment_matches 一样先去掉外层尖括号，否则 ` as ` 总在尖括号之内
+    segment
+        .strip_prefix('<')
+        .and_then(|rest| rest.strip_suffix('>'))
+        .is_some_and(|inner| inner.trim_start().starts_with("impl ") || split_top_level(inner, " as ").is_some())
+}
+
+/// def_path 中的一段是否指向给定的 impl 块
+fn impl_segment_matches(segment: &str, self_ty: &str, trait_name: Option<&str>, ordinal: usize) -> bool {
+    // rustc 的 `{impl#N}`，旧版本为不带序号的 `{{impl}}`
+    if let Some(number) = segment.strip_prefix("{impl#").and_then(|rest| rest.strip_suffix('}')) {
+        return number.parse() == Ok(ordinal);
+    }
+    if segment == "{{impl}}" {
+        return true;
+    }
+    let Some(inner) = segment.strip_prefix('<').and_then(|rest| rest.strip_suffix('>')) else {
+        // 只写类型名时匹配该类型的所有 impl 块
+        return simple_name(segment) == self_ty;
+    };
+    let inner = inner.trim();
+    let (ty, trait_part) = if let Some(impl_body) = inner.strip_prefix("impl ") {
+        match split_top_level(impl_body, " for ") {
+            Some((trait_part, ty)) => (ty, Some(trait_part)),
+            None => (impl_body, None),
+        }
+    } else {
+        match split_top_level(inner, " as ") {
+            Some((ty, trait_part)) => (ty, Some(trait_part)),
+            None => (inner, None),
+        }
+    };
+    simple_name(ty) == self_ty && trait_part.map(simple_name) == trait_name
+}
+
+/// 在尖括号之外查找 separator，返回它前后的部分
+fn split_top_level<'a>(text: &'a str, separator: &str) -> Option<(&'a str, &'a str)> {
+    let mut depth = 0usize;
+    for (index, c) in text.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth = depth.saturating_sub(1),
+            _ if depth == 0 && text[index..].starts_with(separator) => {
+                return Some((&text[..index], &text[index + separator.len()..]));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// 类型或 trait 的简单名称：去掉引用、泛型参数与路径前缀，例如 `&mut std::vec::Vec<T>` -> `Vec`
+fn simple_name(text: &str) -> &str {
+    let mut text = text.trim();
+    for prefix in ["&", "mut ", "dyn ", "'static "] {
+        text = text.trim_start_matches(prefix).trim_start();
+    }
+    let without_generics = match text.find('<') {
+        Some(index) if index > 0 => &text[..index],
+        _ => text,
+    };
+    let last = def_path_segments(without_generics).pop().unwrap_or(without_generics).trim();
+    last.strip_prefix("r#").unwrap_or(last)
+}
+
+/// impl 块 self 类型的简单名称，与 simple_name 的规则一致
+fn type_name(ty: &syn::Type) -> String {
+    match ty {
+        syn::Type::Path(type_path) => match type_path.path.segments.last() {
+            Some(segment) => segment.ident.unraw().to_string(),
+            None => String::new(),
+        },
+        syn::Type::Reference(reference) => type_name(&reference.elem),
+        syn::Type::Paren(paren) => type_name(&paren.elem),
+        other => other.to_token_stream().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn impl_def(self_ty: &str, trait_name: Option<&str>, ordinal: usize, module_path: &[&str]) -> FunctionDef {
+        FunctionDef {
+            module_path: module_path.iter().map(|module| module.to_string()).collect(),
+            container: Container::Impl {
+                self_ty: self_ty.to_owned(),
+                trait_name: trait_name.map(str::to_owned),
+                ordinal,
+            },
+            site: FunctionSite {
+                rel_file: PathBuf::from("src/lib.rs"),
+                start_line: 1,
+                end_line: 1,
+            },
+        }
+    }
+
+    fn index_of(name: &str, defs: Vec<FunctionDef>) -> DefPathIndex {
+        DefPathIndex {
+            by_name: HashMap::from([(name.to_owned(), defs)]),
+        }
+    }
+
+    #[test]
+    fn normalized_segments_keeps_qualified_segments() {
+        assert_eq!(normalized_segments("<T as Trait>::m"), ["<T as Trait>", "m"]);
+        assert_eq!(normalized_segments("<impl Trait for T>::m"), ["<impl Trait for T>", "m"]);
+        assert_eq!(normalized_segments("my_crate::a::<Vec<T> as Trait>::m"), ["my_crate", "a", "<Vec<T> as Trait>", "m"]);
+        assert_eq!(normalized_segments("crate::a::{impl#3}::m"), ["crate", "a", "{impl#3}", "m"]);
+    }
+
+    #[test]
+    fn normalized_segments_drops_generic_args_and_raw_prefix() {
+        assert_eq!(normalized_segments("alloc::vec::Vec::<T>::new"), ["alloc", "vec", "Vec", "new"]);
+        assert_eq!(normalized_segments(" crate::r#type::Foo::<u8>::r#match "), ["crate", "type", "Foo", "match"]);
+        assert_eq!(normalized_segments("crate::f::<fn() -> u8>"), ["crate", "f"]);
+    }
+
+    #[test]
+    fn resolve_qualified_and_numbered_impls() {
+        let index = index_of(
+            "m",
+            vec![impl_def("T", Some("Trait"), 0, &["a"]), impl_def("T", None, 1, &["a"]), impl_def("Vec", None, 0, &[])],
+        );
+        let found = |def_path| index.resolve(def_path, "my-crate").len();
+        assert_eq!(found("<T as Trait>::m"), 1);
+        assert_eq!(found("<impl Trait for T>::m"), 1);
+        assert_eq!(found("<impl a::Trait for &mut T>::m"), 1);
+        assert_eq!(found("<T as Other>::m"), 0);
+        assert_eq!(found("my_crate::a::{impl#1}::m"), 1);
+        assert_eq!(found("crate::a::{impl#2}::m"), 0);
+        assert_eq!(found("crate::a::T::m"), 2);
+        assert_eq!(found("my_crate::Vec::<T>::m"), 1);
+        assert_eq!(found("other_crate::Vec::m"), 0);
+    }
+}
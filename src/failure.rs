@@ -0,0 +1,276 @@
+use std::any::Any;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use csv::{StringRecord, WriterBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::checksum::ChecksumError;
+use crate::input::RawRecord;
+
+pub const FAILURES_FILE_NAME: &str = "failures.jsonl";
+pub const FAILED_RECORDS_FILE_NAME: &str = "records_failed_to_extract.csv";
+pub const FAIL_REASON_FILE_NAME: &str = "records_failed_reason.txt";
+
+/// 失败的类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    /// 输入记录缺少字段或字段无效
+    InvalidRecord,
+    /// 路径改写后得到空路径、绝对路径或离开 crate 根目录的路径
+    PathRewrite,
+    /// crate 名无法解析，或缓存中没有对应的 crate/版本
+    CrateMissing,
+    /// .crate 的 SHA-256 与 crate index 不一致或 index 中没有记录
+    Checksum,
+    /// 指定了 crate index，但 crate 源码来自已解压的目录，无法校验；其中的记录照常提取
+    ChecksumUnverifiable,
+    /// 找到了 crate 但无法打开或解压
+    CrateOpen,
+    /// crate 中没有记录指向的文件
+    FileMissing,
+    /// 读取文件失败
+    Io,
+    ParseError,
+    ParsePanic,
+    /// 记录的起始行上没有函数
+    FunctionNotFound,
+    /// 记录的起始行上没有函数，def_path 指向 crate 中其他位置的函数
+    DefPathMismatch,
+    /// .crate 中的条目因不安全或过大而没有解压
+    UnpackRejected,
+}
+
+impl FailureKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FailureKind::InvalidRecord => "invalid_record",
+            FailureKind::PathRewrite => "path_rewrite",
+            FailureKind::CrateMissing => "crate_missing",
+            FailureKind::Checksum => "checksum",
+            FailureKind::ChecksumUnverifiable => "checksum_unverifiable",
+            FailureKind::CrateOpen => "crate_open",
+            FailureKind::FileMissing => "file_missing",
+            FailureKind::Io => "io",
+            FailureKind::ParseError => "parse_error",
+            FailureKind::ParsePanic => "parse_panic",
+            FailureKind::FunctionNotFound => "function_not_found",
+            FailureKind::DefPathMismatch => "def_path_mismatch",
+            FailureKind::UnpackRejected => "unpack_rejected",
+        }
+    }
+
+    /// 打开 crate 失败时按错误链区分校验失败与其他错误
+    pub fn of_open_error(error: &anyhow::Error) -> FailureKind {
+        if error.chain().any(|cause| cause.is::<ChecksumError>()) {
+            FailureKind::Checksum
+        } else {
+            FailureKind::CrateOpen
+        }
+    }
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 失败发生的位置，未知的部分留空
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FailureLocation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crate_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crate_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub def_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+}
+
+impl fmt::Display for FailureLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(crate_name) = &self.crate_name {
+            match &self.crate_version {
+                Some(version) => parts.push(format!("{} {}", crate_name, version)),
+                None => parts.push(crate_name.clone()),
+            }
+        }
+        if let Some(def_path) = &self.def_path {
+            parts.push(def_path.clone());
+        }
+        match (&self.file, self.line, self.column) {
+            (Some(file), Some(line), Some(column)) => parts.push(format!("{}:{}:{}", file, line, column)),
+            (Some(file), Some(line), None) => parts.push(format!("{}:{}", file, line)),
+            (Some(file), None, _) => parts.push(file.clone()),
+            (None, _, _) => {}
+        }
+        f.write_str(&parts.join(" "))
+    }
+}
+
+/// 一次失败：类别、说明与位置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Failure {
+    pub kind: FailureKind,
+    pub message: String,
+    #[serde(default)]
+    pub location: FailureLocation,
+}
+
+impl Failure {
+    pub fn new(kind: FailureKind, message: impl Into<String>) -> Failure {
+        Failure {
+            kind,
+            message: message.into(),
+            location: FailureLocation::default(),
+        }
+    }
+
+    pub fn at(mut self, location: &FailureLocation) -> Failure {
+        self.location = location.clone();
+        self
+    }
+
+    /// 解析时 panic，说明取 panic 的消息
+    pub fn parse_panic(payload: &(dyn Any + Send), location: &FailureLocation) -> Failure {
+        let panic_reason = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(|s| s.as_str()))
+            .unwrap_or("Unknown panic payload type");
+        Failure::new(FailureKind::ParsePanic, format!("panic when parsing: {}", panic_reason)).at(location)
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.kind, self.message)?;
+        let location = self.location.to_string();
+        if !location.is_empty() {
+            write!(f, " at {}", location)?;
+        }
+        Ok(())
+    }
+}
+
+/// failures.jsonl 中的一行：原始记录与它的失败
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FailureLine {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item_id: Option<String>,
+    #[serde(flatten)]
+    pub failure: Failure,
+    /// 原始记录：有表头的 CSV 为列名到值的对象，无表头时为字符串数组，JSONL 为原对象；与记录无关的失败为 null
+    #[serde(default)]
+    pub record: Value,
+}
+
+/// 把失败同时写入三个文件：failures.jsonl（结构化）、records_failed_reason.txt（供人阅读）
+/// 与 records_failed_to_extract.csv/.jsonl（原始记录，供重新处理）
+pub struct FailureSink {
+    result_root: PathBuf,
+    headers: Option<StringRecord>,
+    count: usize,
+}
+
+impl FailureSink {
+    /// headers 是输入 CSV 的表头，会写入空的失败记录 CSV，方便之后按同一份映射重新读取
+    pub fn open(result_root: &Path, headers: Option<&StringRecord>) -> Result<FailureSink> {
+        let failed_records_path = result_root.join(FAILED_RECORDS_FILE_NAME);
+        if let Some(headers) = headers {
+            if fs::metadata(&failed_records_path).map(|meta| meta.len() == 0).unwrap_or(true) {
+                write_when_fail(&failed_records_path, &RawRecord::Csv(headers.clone()))?;
+            }
+        }
+        Ok(FailureSink {
+            result_root: result_root.to_path_buf(),
+            headers: headers.cloned(),
+            count: 0,
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// 记录一次失败；record 为 None 表示与输入记录无关的失败（例如 scan 中的 crate）
+    pub fn record(&mut self, record: Option<&RawRecord>, item_id: Option<&str>, failure: &Failure) -> Result<()> {
+        self.count += 1;
+        println!("failed {}: {}", self.count, failure);
+        let line = FailureLine {
+            item_id: item_id.map(str::to_owned),
+            failure: failure.clone(),
+            record: record.map(|record| self.record_value(record)).unwrap_or(Value::Null),
+        };
+        let json = serde_json::to_string(&line).context("cannot serialize failure")?;
+        append_line(&self.result_root.join(FAILURES_FILE_NAME), &json)?;
+        let reason = match item_id {
+            Some(item_id) => format!("{} {}", item_id, failure),
+            None => failure.to_string(),
+        };
+        append_line(&self.result_root.join(FAIL_REASON_FILE_NAME), &reason)?;
+        if let Some(record) = record {
+            write_when_fail(&self.result_root.join(FAILED_RECORDS_FILE_NAME), record)?;
+        }
+        Ok(())
+    }
+
+    fn record_value(&self, record: &RawRecord) -> Value {
+        match (record, &self.headers) {
+            (RawRecord::Csv(row), Some(headers)) => Value::Object(
+                headers
+                    .iter()
+                    .zip(row.iter())
+                    .map(|(header, value)| (header.to_owned(), Value::String(value.to_owned())))
+                    .collect(),
+            ),
+            (RawRecord::Csv(row), None) => Value::Array(row.iter().map(|value| Value::String(value.to_owned())).collect()),
+            (RawRecord::Json(line), _) => serde_json::from_str(line).unwrap_or_else(|_| Value::String(line.clone())),
+        }
+    }
+}
+
+/// 向文件追加一行
+fn append_line(path: &Path, line: &str) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)    // 不存在就创建
+        .append(true)    // 以追加模式，不会截断
+        .open(path)
+        .with_context(|| format!("failed to open or create {:?}", path))?;
+    writeln!(file, "{}", line).with_context(|| format!("failed to write {:?}", path))
+}
+
+/// 把失败记录按原样追加到失败文件；JSONL 输入写入同名的 .jsonl 文件
+fn write_when_fail(fail_result_root: &Path, record: &RawRecord) -> Result<()> {
+    match record {
+        RawRecord::Csv(record) => {
+            let failed_file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(fail_result_root)
+                .with_context(|| format!("failed to open or create {:?}", fail_result_root))?;
+            // 使用 csv::Writer 写入单行，不写表头
+            let mut wtr = WriterBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_writer(BufWriter::new(failed_file));
+            wtr.write_record(record)
+                .and_then(|_| wtr.flush().map_err(csv::Error::from))
+                .with_context(|| format!("failed to write {:?}", fail_result_root))
+        }
+        RawRecord::Json(line) => append_line(&fail_result_root.with_extension("jsonl"), line),
+    }
+}
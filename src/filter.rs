@@ -0,0 +1,374 @@
+use std::cmp::Ordering;
+
+use anyhow::{anyhow, bail, Context, Result};
+use regex::Regex;
+
+use crate::input::FunctionRecord;
+
+/// 记录过滤表达式，例如 `safety in [Unsafe, Safe] && crate =~ "^tokio"`
+///
+/// 字段名可以是记录的固有字段（item_id、crate、def_path、file、start_line、safety），
+/// 也可以是输入中的任意原始列（有表头时为列名，否则为从 0 开始的列下标）。
+/// 输入中不存在的字段按空字符串比较。
+#[derive(Debug)]
+pub enum FilterExpr {
+    And(Box<FilterExpr>, Box<FilterExpr>),
+    Or(Box<FilterExpr>, Box<FilterExpr>),
+    Not(Box<FilterExpr>),
+    Compare { field: String, op: CompareOp, value: String },
+    In { field: String, values: Vec<String>, negated: bool },
+    Matches { field: String, regex: Regex, negated: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl FilterExpr {
+    pub fn parse(text: &str) -> Result<FilterExpr> {
+        let tokens = tokenize(text).with_context(|| format!("invalid filter expression {:?}", text))?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser
+            .parse_or()
+            .with_context(|| format!("invalid filter expression {:?}", text))?;
+        if let Some(token) = parser.peek() {
+            bail!("invalid filter expression {:?}: unexpected {:?} at the end", text, token);
+        }
+        Ok(expr)
+    }
+
+    /// `--safety` 的等价表达式：`safety in [...]`
+    pub fn safety_in(values: &[String]) -> FilterExpr {
+        FilterExpr::In {
+            field: "safety".to_owned(),
+            values: values.to_vec(),
+            negated: false,
+        }
+    }
+
+    pub fn and(self, other: FilterExpr) -> FilterExpr {
+        FilterExpr::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn matches(&self, record: &FunctionRecord) -> bool {
+        match self {
+            FilterExpr::And(left, right) => left.matches(record) && right.matches(record),
+            FilterExpr::Or(left, right) => left.matches(record) || right.matches(record),
+            FilterExpr::Not(inner) => !inner.matches(record),
+            FilterExpr::Compare { field, op, value } => {
+                let actual = record.field(field).unwrap_or_default();
+                let ordering = compare_values(&actual, value);
+                match op {
+                    CompareOp::Eq => ordering == Ordering::Equal,
+                    CompareOp::Ne => ordering != Ordering::Equal,
+                    CompareOp::Lt => ordering == Ordering::Less,
+                    CompareOp::Le => ordering != Ordering::Greater,
+                    CompareOp::Gt => ordering == Ordering::Greater,
+                    CompareOp::Ge => ordering != Ordering::Less,
+                }
+            }
+            FilterExpr::In { field, values, negated } => {
+                let actual = record.field(field).unwrap_or_default();
+                values.iter().any(|value| *value == actual) != *negated
+            }
+            FilterExpr::Matches { field, regex, negated } => {
+                let actual = record.field(field).unwrap_or_default();
+                regex.is_match(&actual) != *negated
+            }
+        }
+    }
+}
+
+/// 两边都是整数时按数值比较，否则按字符串比较
+fn compare_values(actual: &str, expected: &str) -> Ordering {
+    match (actual.trim().parse::<i64>(), expected.parse::<i64>()) {
+        (Ok(actual), Ok(expected)) => actual.cmp(&expected),
+        _ => actual.cmp(expected),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Op(&'static str),
+    AndAnd,
+    OrOr,
+    Bang,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < chars.len() {
+        let c = chars[pos];
+        let next = chars.get(pos + 1).copied();
+        if c.is_whitespace() {
+            pos += 1;
+            continue;
+        }
+        let (token, len) = match (c, next) {
+            ('&', Some('&')) => (Token::AndAnd, 2),
+            ('|', Some('|')) => (Token::OrOr, 2),
+            ('=', Some('=')) => (Token::Op("=="), 2),
+            ('=', Some('~')) => (Token::Op("=~"), 2),
+            ('!', Some('=')) => (Token::Op("!="), 2),
+            ('!', Some('~')) => (Token::Op("!~"), 2),
+            ('<', Some('=')) => (Token::Op("<="), 2),
+            ('>', Some('=')) => (Token::Op(">="), 2),
+            ('<', _) => (Token::Op("<"), 1),
+            ('>', _) => (Token::Op(">"), 1),
+            ('!', _) => (Token::Bang, 1),
+            ('(', _) => (Token::LParen, 1),
+            (')', _) => (Token::RParen, 1),
+            ('[', _) => (Token::LBracket, 1),
+            (']', _) => (Token::RBracket, 1),
+            (',', _) => (Token::Comma, 1),
+            ('"', _) | ('\'', _) => {
+                let quote = c;
+                let mut value = String::new();
+                let mut end = pos + 1;
+                loop {
+                    match chars.get(end) {
+                        None => bail!("unterminated string starting at {}", pos),
+                        Some('\\') if chars.get(end + 1).is_some() => {
+                            value.push(chars[end + 1]);
+                            end += 2;
+                        }
+                        Some(&ch) if ch == quote => break,
+                        Some(&ch) => {
+                            value.push(ch);
+                            end += 1;
+                        }
+                    }
+                }
+                (Token::Str(value), end + 1 - pos)
+            }
+            _ if is_word_char(c) => {
+                let len = chars[pos..].iter().take_while(|ch| is_word_char(**ch)).count();
+                (Token::Word(chars[pos..pos + len].iter().collect()), len)
+            }
+            _ => bail!("unexpected character {:?} at {}", c, pos),
+        };
+        tokens.push(token);
+        pos += len;
+    }
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':' | '/' | '*' | '$')
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => bail!("expected {:?}, found {:?}", expected, token),
+            None => bail!("expected {:?}, found end of expression", expected),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<FilterExpr> {
+        let mut left = self.parse_and()?;
+        while self.peek() == Some(&Token::OrOr) {
+            self.pos += 1;
+            let right = self.parse_and()?;
+            left = FilterExpr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<FilterExpr> {
+        let mut left = self.parse_unary()?;
+        while self.peek() == Some(&Token::AndAnd) {
+            self.pos += 1;
+            let right = self.parse_unary()?;
+            left = FilterExpr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<FilterExpr> {
+        match self.peek() {
+            Some(Token::Bang) => {
+                self.pos += 1;
+                Ok(FilterExpr::Not(Box::new(self.parse_unary()?)))
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let expr = self.parse_or()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            _ => self.parse_comparison(),
+        }
+    }
+
+    fn parse_comparison(&mut self) -> Result<FilterExpr> {
+        let field = match self.next() {
+            Some(Token::Word(word)) => word,
+            Some(token) => bail!("expected a field name, found {:?}", token),
+            None => bail!("expected a field name, found end of expression"),
+        };
+        match self.next() {
+            Some(Token::Word(word)) if word == "in" => Ok(FilterExpr::In {
+                field,
+                values: self.parse_list()?,
+                negated: false,
+            }),
+            Some(Token::Word(word)) if word == "not" => {
+                self.expect(Token::Word("in".to_owned()))?;
+                Ok(FilterExpr::In {
+                    field,
+                    values: self.parse_list()?,
+                    negated: true,
+                })
+            }
+            Some(Token::Op(op @ ("=~" | "!~"))) => {
+                let pattern = self.parse_value()?;
+                let regex = Regex::new(&pattern).with_context(|| format!("invalid regex {:?}", pattern))?;
+                Ok(FilterExpr::Matches {
+                    field,
+                    regex,
+                    negated: op == "!~",
+                })
+            }
+            Some(Token::Op(op)) => {
+                let op = match op {
+                    "==" => CompareOp::Eq,
+                    "!=" => CompareOp::Ne,
+                    "<" => CompareOp::Lt,
+                    "<=" => CompareOp::Le,
+                    ">" => CompareOp::Gt,
+                    ">=" => CompareOp::Ge,
+                    _ => unreachable!("tokenizer only produces known operators"),
+                };
+                Ok(FilterExpr::Compare {
+                    field,
+                    op,
+                    value: self.parse_value()?,
+                })
+            }
+            Some(token) => bail!("expected an operator after {:?}, found {:?}", field, token),
+            None => bail!("expected an operator after {:?}, found end of expression", field),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Token::Word(value)) | Some(Token::Str(value)) => Ok(value),
+            Some(token) => bail!("expected a value, found {:?}", token),
+            None => Err(anyhow!("expected a value, found end of expression")),
+        }
+    }
+
+    fn parse_list(&mut self) -> Result<Vec<String>> {
+        self.expect(Token::LBracket)?;
+        let mut values = Vec::new();
+        if self.peek() == Some(&Token::RBracket) {
+            self.pos += 1;
+            return Ok(values);
+        }
+        loop {
+            values.push(self.parse_value()?);
+            match self.next() {
+                Some(Token::Comma) => {}
+                Some(Token::RBracket) => return Ok(values),
+                Some(token) => bail!("expected ',' or ']' in list, found {:?}", token),
+                None => bail!("unterminated list"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn record(crate_name: &str, safety: &str, start_line: usize) -> FunctionRecord {
+        FunctionRecord {
+            item_id: "1".to_owned(),
+            crate_name: crate_name.to_owned(),
+            def_path: format!("{}::f", crate_name.replace('-', "_")),
+            file: "src/lib.rs".to_owned(),
+            start_line,
+            safety: safety.to_owned(),
+            version: None,
+            columns: BTreeMap::from([("7".to_owned(), "x".to_owned())]),
+        }
+    }
+
+    fn eval(text: &str, record: &FunctionRecord) -> bool {
+        FilterExpr::parse(text).unwrap().matches(record)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let expr = FilterExpr::parse("a == 1 || b == 2 && c == 3").unwrap();
+        assert!(matches!(&expr, FilterExpr::Or(_, right) if matches!(**right, FilterExpr::And(..))));
+        let unsafe_tokio = record("tokio", "Unsafe", 10);
+        assert!(eval("crate == serde || crate == tokio && safety == Unsafe", &unsafe_tokio));
+        assert!(!eval("(crate == serde || crate == tokio) && safety == Safe", &unsafe_tokio));
+        assert!(eval("!safety == Safe && !(start_line > 10)", &unsafe_tokio));
+    }
+
+    #[test]
+    fn in_lists_and_numeric_comparison() {
+        let safe = record("serde", "Safe", 42);
+        assert!(eval("safety in [Unsafe, Safe]", &safe));
+        assert!(eval("safety in ['Safe']", &safe));
+        assert!(!eval("safety not in [Safe]", &safe));
+        assert!(!eval("safety in []", &safe));
+        // 整数按数值比较，42 > 9
+        assert!(eval("start_line > 9 && start_line <= 42", &safe));
+        assert!(eval("$7 == x && missing == ''", &safe));
+    }
+
+    #[test]
+    fn regex_matches() {
+        let tokio_util = record("tokio-util", "Safe", 1);
+        assert!(eval(r#"crate =~ "^tokio""#, &tokio_util));
+        assert!(!eval(r#"crate !~ "util$""#, &tokio_util));
+        assert!(eval("def_path =~ '::f$'", &tokio_util));
+        assert!(FilterExpr::parse("crate =~ '('").is_err());
+    }
+
+    #[test]
+    fn rejects_incomplete_expressions() {
+        for text in ["crate == tokio &&", "crate ==", "crate", "safety in [Safe", "(crate == a", "crate == a )", "crate == 'a"] {
+            assert!(FilterExpr::parse(text).is_err(), "{:?} should not parse", text);
+        }
+        let error = format!("{:#}", FilterExpr::parse("crate == tokio ||").unwrap_err());
+        assert!(error.contains("end of expression"), "{}", error);
+    }
+}
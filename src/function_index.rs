@@ -0,0 +1,228 @@
+use syn::spanned::Spanned;
+use syn::{File, ForeignItem, Ident, ImplItem, Item, ItemMod, TraitItem};
+
+use crate::FunctionMacroType;
+
+/// 索引中的一个函数（或宏）：行范围，以及从文件根找到它的下标
+struct IndexEntry {
+    start_line: usize,
+    end_line: usize,
+    /// 此条及之前所有条目的最大结束行，二分查找时使用
+    reach: usize,
+    /// 依次经过的条目下标：前面的是内联模块，最后一个是函数本身或它所在的 impl/trait/extern 块
+    items: Vec<usize>,
+    /// 函数在 impl/trait/extern 块中的下标
+    member: Option<usize>,
+}
+
+impl IndexEntry {
+    fn resolve<'ast>(&self, ast: &'ast File) -> Option<FunctionMacroType<'ast>> {
+        let (&last, outer) = self.items.split_last()?;
+        let mut items = &ast.items;
+        for &index in outer {
+            match items.get(index)? {
+                Item::Mod(ItemMod { content: Some((_, mod_items)), .. }) => items = mod_items,
+                _ => return None,
+            }
+        }
+        match (items.get(last)?, self.member) {
+            (Item::Fn(item_fn), None) => Some(FunctionMacroType::ItemFn(item_fn)),
+            (Item::Macro(item_macro), None) => Some(FunctionMacroType::ItemMacro(item_macro)),
+            (Item::Macro2(item_macro2), None) => Some(FunctionMacroType::ItemMacro2(item_macro2)),
+            (Item::Impl(item_impl), Some(member)) => match item_impl.items.get(member)? {
+                ImplItem::Method(impl_item_method) => Some(FunctionMacroType::ImplItemMethod(impl_item_method)),
+                _ => None,
+            },
+            (Item::Trait(item_trait), Some(member)) => match item_trait.items.get(member)? {
+                TraitItem::Method(trait_item_method) => Some(FunctionMacroType::TraitItemMethod(trait_item_method)),
+                _ => None,
+            },
+            (Item::ForeignMod(item_foreign_mod), Some(member)) => match item_foreign_mod.items.get(member)? {
+                ForeignItem::Fn(foreign_item_fn) => Some(FunctionMacroType::ForeignItemFn(foreign_item_fn)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// 文件中所有函数按行范围建立的索引，每个文件解析后建立一次
+///
+/// 覆盖顶层与内联模块中的函数、宏定义，以及 impl、trait、extern 块中的函数；条目按源码顺序排列。
+#[derive(Default)]
+pub struct FunctionIndex {
+    entries: Vec<IndexEntry>,
+}
+
+impl FunctionIndex {
+    pub fn build(ast: &File) -> FunctionIndex {
+        let mut index = FunctionIndex::default();
+        index.add_items(&ast.items, &mut Vec::new());
+        let mut reach = 0;
+        for entry in &mut index.entries {
+            reach = reach.max(entry.end_line);
+            entry.reach = reach;
+        }
+        index
+    }
+
+    fn add_items(&mut self, items: &[Item], path: &mut Vec<usize>) {
+        for (index, item) in items.iter().enumerate() {
+            path.push(index);
+            match item {
+                // 宏只收录有名字的定义（`macro_rules!`），与 scan 和 def_path 的规则相同；宏调用不是函数
+                Item::Fn(_) | Item::Macro2(_) => self.push(item, path, None),
+                Item::Macro(item_macro) if item_macro.ident.is_some() => self.push(item, path, None),
+                Item::Mod(item_mod) => {
+                    if let Some((_, mod_items)) = &item_mod.content {
+                        self.add_items(mod_items, path);
+                    }
+                }
+                Item::Impl(item_impl) => {
+                    for (member, impl_item) in item_impl.items.iter().enumerate() {
+                        // ImplItem::Macro 暂不作为函数处理
+                        if let ImplItem::Method(impl_item_method) = impl_item {
+                            self.push(impl_item_method, path, Some(member));
+                        }
+                    }
+                }
+                Item::Trait(item_trait) => {
+                    for (member, trait_item) in item_trait.items.iter().enumerate() {
+                        if let TraitItem::Method(trait_item_method) = trait_item {
+                            self.push(trait_item_method, path, Some(member));
+                        }
+                    }
+                }
+                Item::ForeignMod(item_foreign_mod) => {
+                    for (member, foreign_item) in item_foreign_mod.items.iter().enumerate() {
+                        if let ForeignItem::Fn(foreign_item_fn) = foreign_item {
+                            self.push(foreign_item_fn, path, Some(member));
+                        }
+                    }
+                }
+                _ => {}
+            }
+            path.pop();
+        }
+    }
+
+    fn push(&mut self, node: &impl Spanned, path: &[usize], member: Option<usize>) {
+        let span = node.span();
+        self.entries.push(IndexEntry {
+            start_line: span.start().line,
+            end_line: span.end().line,
+            reach: 0,
+            items: path.to_vec(),
+            member,
+        });
+    }
+
+    /// 查找行范围包含 line 的函数，有多个时取源码中靠前的一个；ast 必须是建立索引时的文件
+    pub fn find<'ast>(&self, ast: &'ast File, line: usize) -> Option<FunctionMacroType<'ast>> {
+        self.entry_at(line).and_then(|entry| entry.resolve(ast))
+    }
+
+    /// find 找到的函数在文件中所处的内联模块，由外到内
+    pub fn inline_modules<'ast>(&self, ast: &'ast File, line: usize) -> Vec<&'ast Ident> {
+        let Some(entry) = self.entry_at(line) else {
+            return Vec::new();
+        };
+        let mut modules = Vec::new();
+        let mut items = &ast.items;
+        for &index in &entry.items[..entry.items.len().saturating_sub(1)] {
+            match items.get(index) {
+                Some(Item::Mod(ItemMod { ident, content: Some((_, mod_items)), .. })) => {
+                    modules.push(ident);
+                    items = mod_items;
+                }
+                _ => break,
+            }
+        }
+        modules
+    }
+
+    fn entry_at(&self, line: usize) -> Option<&IndexEntry> {
+        // 函数之间互不嵌套，reach 之前的条目都在 line 之前结束
+        let first = self.entries.partition_point(|entry| entry.reach < line);
+        self.entries[first..]
+            .iter()
+            .take_while(|entry| entry.start_line <= line)
+            .find(|entry| entry.end_line >= line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+mod outer {
+    pub struct S;
+    impl S {
+        pub fn a(&self) {
+            fn helper() {}
+        }
+
+        pub fn b(&self) {}
+    }
+    mod deeper {
+        pub fn c() {}
+    }
+}
+pub fn d() {
+    fn e() {}
+}
+";
+
+    fn name_at(index: &FunctionIndex, ast: &File, line: usize) -> Option<String> {
+        index.find(ast, line).map(|func| func.name_and_lines().0)
+    }
+
+    #[test]
+    fn finds_the_outermost_function_containing_the_line() {
+        let ast = syn::parse_file(SOURCE).unwrap();
+        let index = FunctionIndex::build(&ast);
+        let names: Vec<Option<String>> = (1..=17).map(|line| name_at(&index, &ast, line)).collect();
+        let expected = [
+            None,
+            None,
+            None,
+            Some("a"),
+            Some("a"),
+            Some("a"),
+            None,
+            Some("b"),
+            None,
+            None,
+            Some("c"),
+            None,
+            None,
+            Some("d"),
+            Some("d"),
+            Some("d"),
+            None,
+        ];
+        assert_eq!(names, expected.map(|name| name.map(str::to_owned)));
+        let modules: Vec<String> = index.inline_modules(&ast, 11).iter().map(|ident| ident.to_string()).collect();
+        assert_eq!(modules, ["outer", "deeper"]);
+        assert_eq!(index.inline_modules(&ast, 5).len(), 1);
+        assert!(index.inline_modules(&ast, 15).is_empty());
+    }
+
+    #[test]
+    fn entry_at_uses_the_running_maximum_of_end_lines() {
+        // 条目的结束行不单调时，二分查找不能越过仍然覆盖 line 的靠前条目
+        let mut index = FunctionIndex::default();
+        let mut reach = 0;
+        for (start_line, end_line) in [(1, 20), (3, 5), (8, 9), (22, 23)] {
+            reach = reach.max(end_line);
+            index.entries.push(IndexEntry { start_line, end_line, reach, items: Vec::new(), member: None });
+        }
+        let found = |line| index.entry_at(line).map(|entry| (entry.start_line, entry.end_line));
+        assert_eq!(found(4), Some((1, 20)));
+        assert_eq!(found(15), Some((1, 20)));
+        assert_eq!(found(21), None);
+        assert_eq!(found(23), Some((22, 23)));
+        assert_eq!(found(24), None);
+    }
+}
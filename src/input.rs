@@ -0,0 +1,489 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Lines};
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use csv::{ReaderBuilder, StringRecord};
+use serde::Deserialize;
+
+/// 输入中的一条函数记录，与具体的输入格式和列布局无关
+#[derive(Debug, Clone)]
+pub struct FunctionRecord {
+    pub item_id: String,
+    pub crate_name: String,
+    pub def_path: String,
+    pub file: String,
+    pub start_line: usize,
+    pub safety: String,
+    /// crate 版本；输入中没有版本列时为 None
+    pub version: Option<String>,
+    /// 输入中的全部原始列，有表头时以列名为键，否则以列下标为键
+    pub columns: BTreeMap<String, String>,
+}
+
+impl FunctionRecord {
+    /// 按名称取字段值，供过滤表达式使用；`$3` 与 `3` 都表示下标为 3 的原始列
+    pub fn field(&self, name: &str) -> Option<Cow<'_, str>> {
+        match name {
+            "item_id" => Some(Cow::Borrowed(&self.item_id)),
+            "crate" | "crate_name" => Some(Cow::Borrowed(&self.crate_name)),
+            "def_path" => Some(Cow::Borrowed(&self.def_path)),
+            "file" => Some(Cow::Borrowed(&self.file)),
+            "start_line" | "line" => Some(Cow::Owned(self.start_line.to_string())),
+            "safety" => Some(Cow::Borrowed(&self.safety)),
+            "version" => self.version.as_deref().map(Cow::Borrowed),
+            _ => self
+                .columns
+                .get(name.trim_start_matches('$'))
+                .map(|value| Cow::Borrowed(value.as_str())),
+        }
+    }
+}
+
+/// 列引用：既可以是表头中的列名，也可以是从 0 开始的列下标
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ColumnRef {
+    Index(usize),
+    Name(String),
+}
+
+/// 列映射文件（TOML 或 JSON）的内容，未出现的字段使用默认列名
+///
+/// version 是可选列：未在映射中指定时，只在表头中存在 `version` 列时才读取。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColumnMapping {
+    pub item_id: ColumnRef,
+    #[serde(rename = "crate", alias = "crate_name")]
+    pub crate_name: ColumnRef,
+    pub def_path: ColumnRef,
+    pub file: ColumnRef,
+    pub start_line: ColumnRef,
+    pub safety: ColumnRef,
+    pub version: Option<ColumnRef>,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        ColumnMapping {
+            item_id: ColumnRef::Name("item_id".to_owned()),
+            crate_name: ColumnRef::Name("crate".to_owned()),
+            def_path: ColumnRef::Name("def_path".to_owned()),
+            file: ColumnRef::Name("file".to_owned()),
+            start_line: ColumnRef::Name("start_line".to_owned()),
+            safety: ColumnRef::Name("safety".to_owned()),
+            version: None,
+        }
+    }
+}
+
+impl ColumnMapping {
+    /// 没有表头的旧版函数 CSV 的固定列布局
+    pub fn legacy() -> Self {
+        ColumnMapping {
+            item_id: ColumnRef::Index(0),
+            crate_name: ColumnRef::Index(1),
+            def_path: ColumnRef::Index(3),
+            file: ColumnRef::Index(9),
+            start_line: ColumnRef::Index(10),
+            safety: ColumnRef::Index(12),
+            version: None,
+        }
+    }
+
+    /// 按扩展名读取 .toml 或 .json 格式的列映射文件
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("cannot read column mapping {:?}", path))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => toml::from_str(&text)
+                .with_context(|| format!("cannot parse column mapping {:?}", path)),
+            Some(ext) if ext.eq_ignore_ascii_case("json") => serde_json::from_str(&text)
+                .with_context(|| format!("cannot parse column mapping {:?}", path)),
+            _ => bail!("column mapping {:?} must be a .toml or .json file", path),
+        }
+    }
+
+    fn uses_column_indexes(&self) -> bool {
+        [&self.item_id, &self.crate_name, &self.def_path, &self.file, &self.start_line, &self.safety]
+            .into_iter()
+            .chain(self.version.as_ref())
+            .any(|column| matches!(column, ColumnRef::Index(_)))
+    }
+
+    /// 在处理任何记录之前对照表头检查映射，所有缺失的列一次性报告
+    pub fn resolve(&self, headers: Option<&StringRecord>) -> Result<ResolvedColumns> {
+        let mut missing = Vec::new();
+        let mut resolve_one = |field: &str, column: &ColumnRef| -> usize {
+            match column {
+                ColumnRef::Index(index) => {
+                    if let Some(headers) = headers {
+                        if *index >= headers.len() {
+                            missing.push(format!("{} (column index {})", field, index));
+                        }
+                    }
+                    *index
+                }
+                ColumnRef::Name(name) => {
+                    match headers.and_then(|headers| headers.iter().position(|header| header.trim() == name)) {
+                        Some(index) => index,
+                        None => {
+                            missing.push(format!("{} (column \"{}\")", field, name));
+                            0
+                        }
+                    }
+                }
+            }
+        };
+        let resolved = ResolvedColumns {
+            headers: headers.map(|headers| headers.iter().map(|header| header.trim().to_owned()).collect()),
+            item_id: resolve_one("item_id", &self.item_id),
+            crate_name: resolve_one("crate", &self.crate_name),
+            def_path: resolve_one("def_path", &self.def_path),
+            file: resolve_one("file", &self.file),
+            start_line: resolve_one("start_line", &self.start_line),
+            safety: resolve_one("safety", &self.safety),
+            version: match &self.version {
+                Some(version) => Some(resolve_one("version", version)),
+                None => headers.and_then(|headers| headers.iter().position(|header| header.trim() == "version")),
+            },
+        };
+        if !missing.is_empty() {
+            let available = match headers {
+                Some(headers) => headers.iter().collect::<Vec<_>>().join(", "),
+                None => "<no header row, use --headers or column indexes>".to_owned(),
+            };
+            bail!(
+                "input is missing required columns: {}\navailable columns: {}",
+                missing.join(", "),
+                available
+            );
+        }
+        Ok(resolved)
+    }
+}
+
+/// 已经对照表头解析成列下标的映射
+#[derive(Debug, Clone)]
+pub struct ResolvedColumns {
+    headers: Option<Vec<String>>,
+    item_id: usize,
+    crate_name: usize,
+    def_path: usize,
+    file: usize,
+    start_line: usize,
+    safety: usize,
+    version: Option<usize>,
+}
+
+impl ResolvedColumns {
+    /// 记录无法完整解析时，仍尽量取出 item_id 以便写入进度日志
+    pub fn item_id<'a>(&self, record: &'a StringRecord) -> Option<&'a str> {
+        record.get(self.item_id)
+    }
+
+    /// 按映射从一行 CSV 中取出函数记录，列不够或行号不是数字时返回错误
+    pub fn read(&self, record: &StringRecord) -> Result<FunctionRecord> {
+        let get = |field: &str, index: usize| -> Result<String> {
+            record.get(index).map(|value| value.to_owned()).ok_or_else(|| {
+                anyhow!(
+                    "record has {} columns but {} is mapped to column {}",
+                    record.len(),
+                    field,
+                    index
+                )
+            })
+        };
+        let start_line_text = get("start_line", self.start_line)?;
+        let start_line = start_line_text
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid start line {:?}", start_line_text))?;
+        Ok(FunctionRecord {
+            item_id: get("item_id", self.item_id)?,
+            crate_name: get("crate", self.crate_name)?,
+            def_path: get("def_path", self.def_path)?,
+            file: get("file", self.file)?,
+            start_line,
+            safety: get("safety", self.safety)?,
+            version: match self.version {
+                Some(index) => Some(get("version", index)?).filter(|version| !version.trim().is_empty()),
+                None => None,
+            },
+            columns: record
+                .iter()
+                .enumerate()
+                .map(|(index, value)| {
+                    let name = match self.headers.as_ref().and_then(|headers| headers.get(index)) {
+                        Some(header) => header.clone(),
+                        None => index.to_string(),
+                    };
+                    (name, value.to_owned())
+                })
+                .collect(),
+        })
+    }
+}
+
+/// 函数记录文件的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum InputFormat {
+    Csv,
+    Jsonl,
+}
+
+impl InputFormat {
+    /// 按扩展名推断格式：.jsonl / .ndjson 为 JSON Lines，其余按 CSV 处理
+    pub fn detect(path: &Path) -> InputFormat {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("jsonl") || ext.eq_ignore_ascii_case("ndjson") => {
+                InputFormat::Jsonl
+            }
+            _ => InputFormat::Csv,
+        }
+    }
+}
+
+/// 输入中的原始一行，失败时按原样写回失败记录文件
+#[derive(Debug, Clone)]
+pub enum RawRecord {
+    Csv(StringRecord),
+    Json(String),
+}
+
+/// 从输入中读出的一行：原始内容，以及按列映射解析出的函数记录
+pub struct InputRow {
+    pub raw: RawRecord,
+    /// 解析失败时仍尽量取出的 item_id，用于写入进度日志
+    pub item_id: Option<String>,
+    pub record: Result<FunctionRecord>,
+}
+
+enum SourceReader {
+    Csv {
+        records: csv::StringRecordsIntoIter<fs::File>,
+        columns: ResolvedColumns,
+    },
+    Jsonl {
+        lines: Lines<BufReader<fs::File>>,
+        mapping: ColumnMapping,
+    },
+}
+
+/// CSV 与 JSON Lines 两种输入的统一入口，逐行产出 InputRow
+pub struct RecordSource {
+    reader: SourceReader,
+    headers: Option<StringRecord>,
+}
+
+impl RecordSource {
+    /// 打开输入文件，并在读取任何记录之前检查列映射
+    ///
+    /// 对 JSONL 输入，映射中的字段必须是键名，并以第一行对象的键作为检查依据。
+    pub fn open(path: &Path, format: InputFormat, has_headers: bool, column_map: Option<&Path>) -> Result<RecordSource> {
+        match format {
+            InputFormat::Csv => {
+                let mut reader = ReaderBuilder::new()
+                    .has_headers(has_headers)
+                    .flexible(true)
+                    .from_path(path)
+                    .with_context(|| format!("Unable to read CSV file {:?}", path))?;
+                let mapping = match column_map {
+                    Some(column_map) => ColumnMapping::from_file(column_map)?,
+                    None if has_headers => ColumnMapping::default(),
+                    None => ColumnMapping::legacy(),
+                };
+                let headers = if has_headers {
+                    Some(reader.headers().with_context(|| format!("Unable to read CSV header of {:?}", path))?.clone())
+                } else {
+                    None
+                };
+                let columns = mapping.resolve(headers.as_ref())?;
+                Ok(RecordSource {
+                    reader: SourceReader::Csv {
+                        records: reader.into_records(),
+                        columns,
+                    },
+                    headers,
+                })
+            }
+            InputFormat::Jsonl => {
+                let mapping = match column_map {
+                    Some(column_map) => ColumnMapping::from_file(column_map)?,
+                    None => ColumnMapping::default(),
+                };
+                if mapping.uses_column_indexes() {
+                    bail!("JSONL input can only map fields by key name, not by column index");
+                }
+                let open = || -> Result<Lines<BufReader<fs::File>>> {
+                    let file = fs::File::open(path).with_context(|| format!("Unable to read JSONL file {:?}", path))?;
+                    Ok(BufReader::new(file).lines())
+                };
+                let first_line = open()?
+                    .map(|line| line.with_context(|| format!("Unable to read JSONL file {:?}", path)))
+                    .find(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+                    .transpose()?;
+                if let Some(first_line) = first_line {
+                    let (keys, _) = json_line_to_columns(&first_line)
+                        .with_context(|| format!("invalid first record in {:?}", path))?;
+                    mapping.resolve(Some(&keys))?;
+                }
+                Ok(RecordSource {
+                    reader: SourceReader::Jsonl { lines: open()?, mapping },
+                    headers: None,
+                })
+            }
+        }
+    }
+
+    /// CSV 表头；JSONL 或无表头 CSV 时为 None
+    pub fn headers(&self) -> Option<&StringRecord> {
+        self.headers.as_ref()
+    }
+}
+
+impl Iterator for RecordSource {
+    /// 外层错误表示读取本身失败（无法得到原始行），内层错误表示该行不是合法的函数记录
+    type Item = Result<InputRow>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.reader {
+            SourceReader::Csv { records, columns } => {
+                let record = match records.next()? {
+                    Ok(record) => record,
+                    Err(e) => return Some(Err(e.into())),
+                };
+                Some(Ok(InputRow {
+                    item_id: columns.item_id(&record).map(|item_id| item_id.to_owned()),
+                    record: columns.read(&record),
+                    raw: RawRecord::Csv(record),
+                }))
+            }
+            SourceReader::Jsonl { lines, mapping } => loop {
+                let line = match lines.next()? {
+                    Ok(line) => line,
+                    Err(e) => return Some(Err(e.into())),
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let (item_id, record) = match json_line_to_columns(&line) {
+                    Ok((keys, values)) => match mapping.resolve(Some(&keys)) {
+                        Ok(columns) => (
+                            columns.item_id(&values).map(|item_id| item_id.to_owned()),
+                            columns.read(&values),
+                        ),
+                        Err(e) => (None, Err(e)),
+                    },
+                    Err(e) => (None, Err(e)),
+                };
+                return Some(Ok(InputRow {
+                    raw: RawRecord::Json(line),
+                    item_id,
+                    record,
+                }));
+            },
+        }
+    }
+}
+
+/// 把一行 JSON 对象拆成（键，值）两行，嵌套的值保留其 JSON 文本
+fn json_line_to_columns(line: &str) -> Result<(StringRecord, StringRecord)> {
+    let object: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(line).context("record is not a JSON object")?;
+    let mut keys = StringRecord::new();
+    let mut values = StringRecord::new();
+    for (key, value) in object {
+        keys.push_field(&key);
+        match value {
+            serde_json::Value::String(text) => values.push_field(&text),
+            serde_json::Value::Null => values.push_field(""),
+            other => values.push_field(&other.to_string()),
+        }
+    }
+    Ok((keys, values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_mapping_reads_fixed_column_indexes() {
+        let columns = ColumnMapping::legacy().resolve(None).unwrap();
+        let row = StringRecord::from(vec!["7", "serde", "x", "serde::de::f", "", "", "", "", "", "/r/src/de.rs", " 42 ", "", "Unsafe"]);
+        let record = columns.read(&row).unwrap();
+        assert_eq!(
+            (record.item_id.as_str(), record.crate_name.as_str(), record.def_path.as_str()),
+            ("7", "serde", "serde::de::f")
+        );
+        assert_eq!((record.file.as_str(), record.start_line, record.safety.as_str()), ("/r/src/de.rs", 42, "Unsafe"));
+        assert_eq!(record.version, None);
+        assert_eq!(record.columns["2"], "x");
+
+        let short = StringRecord::from(vec!["7", "serde", "x", "serde::de::f"]);
+        let error = format!("{:#}", columns.read(&short).unwrap_err());
+        assert!(error.contains("record has 4 columns but start_line is mapped to column 10"), "{}", error);
+    }
+
+    #[test]
+    fn header_mapping_finds_columns_by_name() {
+        let headers = StringRecord::from(vec!["safety", " start_line", "file", "def_path", "crate", "item_id", "version"]);
+        let columns = ColumnMapping::default().resolve(Some(&headers)).unwrap();
+        let record = columns.read(&StringRecord::from(vec!["Safe", "3", "src/lib.rs", "a::f", "a", "1", ""])).unwrap();
+        assert_eq!((record.item_id.as_str(), record.crate_name.as_str(), record.start_line), ("1", "a", 3));
+        // 空的 version 视为没有版本
+        assert_eq!(record.version, None);
+        assert_eq!(record.field("start_line").as_deref(), Some("3"));
+        let record = columns.read(&StringRecord::from(vec!["Safe", "3", "src/lib.rs", "a::f", "a", "1", "1.2.3"])).unwrap();
+        assert_eq!(record.version.as_deref(), Some("1.2.3"));
+
+        // 映射中可以混用列名与列下标
+        let mapping: ColumnMapping = toml::from_str("crate = 4\nfile = \"def_path\"\ndef_path = \"file\"").unwrap();
+        let record = mapping.resolve(Some(&headers)).unwrap().read(&StringRecord::from(vec!["Safe", "3", "src/lib.rs", "a::f", "a", "1", ""])).unwrap();
+        assert_eq!((record.crate_name.as_str(), record.file.as_str(), record.def_path.as_str()), ("a", "a::f", "src/lib.rs"));
+    }
+
+    #[test]
+    fn header_mapping_reports_all_missing_columns() {
+        let headers = StringRecord::from(vec!["item_id", "crate", "path", "line"]);
+        let error = format!("{:#}", ColumnMapping::default().resolve(Some(&headers)).unwrap_err());
+        for missing in ["def_path (column \"def_path\")", "file (column \"file\")", "start_line (column \"start_line\")", "safety (column \"safety\")"] {
+            assert!(error.contains(missing), "{}", error);
+        }
+        assert!(error.contains("available columns: item_id, crate, path, line"), "{}", error);
+        assert!(ColumnMapping::legacy().resolve(Some(&headers)).is_err());
+    }
+
+    #[test]
+    fn jsonl_records_with_missing_fields_fail_alone() {
+        let path = std::env::temp_dir().join(format!("input_missing_field_{}.jsonl", std::process::id()));
+        fs::write(
+            &path,
+            concat!(
+                "{\"item_id\": 1, \"crate\": \"a\", \"def_path\": \"a::f\", \"file\": \"src/lib.rs\", \"start_line\": 3, \"safety\": \"Safe\", \"version\": null}\n",
+                "\n",
+                "{\"item_id\": 2, \"crate\": \"a\", \"def_path\": \"a::g\", \"start_line\": 9, \"safety\": \"Safe\"}\n",
+                "[1, 2]\n",
+                "{\"item_id\": \"3\", \"crate\": \"b\", \"def_path\": \"b::h\", \"file\": \"src/lib.rs\", \"start_line\": \"1\", \"safety\": \"Safe\", \"version\": \"0.1.0\"}\n",
+            ),
+        )
+        .unwrap();
+        let rows: Vec<InputRow> = RecordSource::open(&path, InputFormat::Jsonl, false, None).unwrap().map(Result::unwrap).collect();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(rows.len(), 4);
+        let first = rows[0].record.as_ref().unwrap();
+        assert_eq!((first.item_id.as_str(), first.start_line, first.version.as_deref()), ("1", 3, None));
+        // 缺少 file 的记录单独失败，原始行原样保留以便写回失败记录文件
+        let error = format!("{:#}", rows[1].record.as_ref().unwrap_err());
+        assert!(error.contains("file (column \"file\")"), "{}", error);
+        assert!(matches!(&rows[1].raw, RawRecord::Json(line) if line.contains("a::g")));
+        assert!(rows[2].record.is_err());
+        let last = rows[3].record.as_ref().unwrap();
+        assert_eq!((last.crate_name.as_str(), last.version.as_deref()), ("b", Some("0.1.0")));
+    }
+}
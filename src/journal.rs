@@ -0,0 +1,173 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+pub const JOURNAL_FILE_NAME: &str = "progress_journal.jsonl";
+
+/// 日志中的一行：某条记录已经写入结果或失败文件，或某个 crate 的结果已经落盘
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum JournalEntry {
+    Item { item_id: String },
+    /// 输入中第 last_record 条（从 1 开始）及之前属于该 crate 的记录都已完成
+    Crate { crate_name: String, last_record: usize },
+}
+
+/// 结果目录中的断点续跑日志
+///
+/// 每条记录只有在它的结果（或失败原因）写入磁盘之后才会被记入日志，
+/// 因此 `--resume` 时跳过日志中的 item_id 不会重复追加 `result-<crate>.json`。
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    done_items: HashSet<String>,
+    /// crate 名 -> 已完成到的输入记录序号
+    done_crates: HashMap<String, usize>,
+}
+
+impl Journal {
+    /// 打开结果目录中的日志；`resume` 为 false 时清空旧日志重新开始
+    pub fn open(result_root: &Path, resume: bool) -> Result<Journal> {
+        let path = result_root.join(JOURNAL_FILE_NAME);
+        let mut done_items = HashSet::new();
+        let mut done_crates = HashMap::new();
+        if resume && path.exists() {
+            let reader = BufReader::new(
+                File::open(&path).with_context(|| format!("cannot open journal {:?}", path))?,
+            );
+            for (line_index, line) in reader.lines().enumerate() {
+                let line = line.with_context(|| format!("cannot read journal {:?}", path))?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                // 上次运行中断时最后一行可能只写了一半，跳过即可
+                match serde_json::from_str::<JournalEntry>(&line) {
+                    Ok(JournalEntry::Item { item_id }) => {
+                        done_items.insert(item_id);
+                    }
+                    Ok(JournalEntry::Crate { crate_name, last_record }) => {
+                        let done_through: &mut usize = done_crates.entry(crate_name).or_default();
+                        *done_through = (*done_through).max(last_record);
+                    }
+                    Err(e) => {
+                        eprintln!("skip broken journal line {} in {:?}: {}", line_index + 1, path, e);
+                    }
+                }
+            }
+        } else if path.exists() {
+            fs::remove_file(&path).with_context(|| format!("cannot reset journal {:?}", path))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("cannot open or create journal {:?}", path))?;
+        Ok(Journal {
+            path,
+            file,
+            done_items,
+            done_crates,
+        })
+    }
+
+    pub fn is_item_done(&self, item_id: &str) -> bool {
+        self.done_items.contains(item_id)
+    }
+
+    /// 输入中第 record_index 条记录所属的 crate 是否已经完成；同一个 crate 在输入中不连续出现时，
+    /// 只有已经处理过的那一段算作完成
+    pub fn is_crate_done(&self, crate_name: &str, record_index: usize) -> bool {
+        self.done_crates.get(crate_name).is_some_and(|&last_record| record_index <= last_record)
+    }
+
+    pub fn done_item_count(&self) -> usize {
+        self.done_items.len()
+    }
+
+    pub fn done_crate_count(&self) -> usize {
+        self.done_crates.len()
+    }
+
+    pub fn mark_item_done(&mut self, item_id: &str) -> Result<()> {
+        self.append(&JournalEntry::Item {
+            item_id: item_id.to_owned(),
+        })?;
+        self.done_items.insert(item_id.to_owned());
+        Ok(())
+    }
+
+    pub fn mark_crate_done(&mut self, crate_name: &str, last_record: usize) -> Result<()> {
+        self.append(&JournalEntry::Crate {
+            crate_name: crate_name.to_owned(),
+            last_record,
+        })?;
+        let done_through = self.done_crates.entry(crate_name.to_owned()).or_default();
+        *done_through = (*done_through).max(last_record);
+        Ok(())
+    }
+
+    fn append(&mut self, entry: &JournalEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry).context("cannot serialize journal entry")?;
+        line.push('\n');
+        // 每行立即刷新，进程被杀死时最多丢失最后一行
+        self.file
+            .write_all(line.as_bytes())
+            .and_then(|_| self.file.flush())
+            .with_context(|| format!("cannot write journal {:?}", self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("journal_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn crate_entries_without_last_record_are_skipped() {
+        let root = result_root("last_record");
+        fs::write(
+            root.join(JOURNAL_FILE_NAME),
+            concat!(
+                "{\"kind\":\"item\",\"item_id\":\"7\"}\n",
+                "{\"kind\":\"crate\",\"crate_name\":\"old\"}\n",
+                "{\"kind\":\"crate\",\"crate_name\":\"serde\",\"last_record\":5}\n",
+                "{\"kind\":\"crate\",\"crate_name\":\"serde\",\"last_record\":3}\n",
+                "{\"kind\":\"item\",\"item_",
+            ),
+        )
+        .unwrap();
+        let journal = Journal::open(&root, true).unwrap();
+        assert!(journal.is_item_done("7"));
+        assert_eq!((journal.done_item_count(), journal.done_crate_count()), (1, 1));
+        assert!(!journal.is_crate_done("old", 1));
+        assert!(journal.is_crate_done("serde", 5));
+        assert!(!journal.is_crate_done("serde", 6));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn reopening_without_resume_starts_over() {
+        let root = result_root("reset");
+        let mut journal = Journal::open(&root, false).unwrap();
+        journal.mark_item_done("1").unwrap();
+        journal.mark_crate_done("serde", 4).unwrap();
+        drop(journal);
+
+        let journal = Journal::open(&root, true).unwrap();
+        assert!(journal.is_item_done("1") && journal.is_crate_done("serde", 4));
+        drop(journal);
+        let journal = Journal::open(&root, false).unwrap();
+        assert!(!journal.is_item_done("1") && !journal.is_crate_done("serde", 4));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
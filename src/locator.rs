@@ -0,0 +1,101 @@
+use std::path::Path;
+
+use syn::ext::IdentExt;
+use syn::File;
+
+use crate::ast_cache::AstCache;
+use crate::def_path::{DefPathIndex, FunctionSite, LocateBy};
+use crate::failure::{Failure, FailureKind, FailureLocation};
+use crate::function_index::FunctionIndex;
+use crate::module_tree::ModuleTree;
+use crate::vfs::CrateFiles;
+
+/// 按 def_path 与模块树定位记录中的函数，extract 与 retry 共用
+///
+/// 模块树与 def_path 索引在当前 crate 第一次查找时建立，切换 crate 时须调用 clear。
+pub struct FunctionLocator {
+    locate_by: LocateBy,
+    module_tree: Option<ModuleTree>,
+    def_path_index: Option<DefPathIndex>,
+}
+
+impl FunctionLocator {
+    pub fn new(locate_by: LocateBy) -> FunctionLocator {
+        FunctionLocator {
+            locate_by,
+            module_tree: None,
+            def_path_index: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.module_tree = None;
+        self.def_path_index = None;
+    }
+
+    /// def_path 指向的所有位置，`--locate-by line` 时为空；同时建立 module_path 使用的模块树
+    pub fn def_path_sites(&mut self, crate_files: &CrateFiles, ast_cache: &mut AstCache, def_path: &str, crate_name: &str) -> Vec<FunctionSite> {
+        let modules = self.module_tree.get_or_insert_with(|| ModuleTree::build(crate_files, ast_cache));
+        if self.locate_by == LocateBy::Line {
+            return Vec::new();
+        }
+        let def_paths = self.def_path_index.get_or_insert_with(|| DefPathIndex::build(modules, crate_files, ast_cache));
+        def_paths.resolve(def_path, crate_name).into_iter().cloned().collect()
+    }
+
+    /// 函数的模块路径（不含 crate 名）：文件在模块树中的路径加上文件内的内联模块
+    ///
+    /// 文件不在模块树中，或还没有调用 def_path_sites 建立模块树时返回 None。
+    pub fn module_path(&self, rel_file: &Path, functions: &FunctionIndex, ast: &File, line: usize) -> Option<Vec<String>> {
+        let mut module_path = self.module_tree.as_ref()?.module_path(rel_file)?.to_vec();
+        module_path.extend(functions.inline_modules(ast, line).iter().map(|ident| ident.unraw().to_string()));
+        Some(module_path)
+    }
+}
+
+/// 替代记录中文件与起始行的候选：def_path 只指向一处时直接采用，即使文件或行号与记录不同；
+/// 指向多处时只采用包含记录的文件与行的候选，没有候选时返回 None，按记录的文件与行号查找
+pub fn locate_site<'a>(sites: &'a [FunctionSite], rel_file: &str, line: usize) -> Option<&'a FunctionSite> {
+    match sites {
+        [site] => Some(site),
+        _ => sites.iter().find(|site| site.contains(Path::new(rel_file), line)),
+    }
+}
+
+/// 记录的起始行上没有函数时的失败；def_path 指向 crate 中其他位置时一并报告这些位置
+pub fn function_not_found(sites: &[FunctionSite], line: usize, file_path: &Path, location: &FailureLocation) -> Failure {
+    if sites.is_empty() {
+        return Failure::new(FailureKind::FunctionNotFound, format!("no function starts at line {} of {:?}", line, file_path)).at(location);
+    }
+    let resolved: Vec<String> = sites.iter().map(|site| format!("{}:{}", site.rel_file.display(), site.start_line)).collect();
+    Failure::new(
+        FailureKind::DefPathMismatch,
+        format!("no function starts at line {} of {:?}, def_path resolves to {}", line, file_path, resolved.join(", ")),
+    )
+    .at(location)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn site(rel_file: &str, start_line: usize, end_line: usize) -> FunctionSite {
+        FunctionSite { rel_file: PathBuf::from(rel_file), start_line, end_line }
+    }
+
+    #[test]
+    fn lone_site_replaces_stale_file_and_line() {
+        let sites = [site("src/top.rs", 6, 8)];
+        assert_eq!(locate_site(&sites, "src/lib.rs", 3), Some(&sites[0]));
+    }
+
+    #[test]
+    fn ambiguous_sites_need_the_record_line() {
+        let sites = [site("src/a.rs", 1, 3), site("src/b.rs", 10, 20)];
+        assert_eq!(locate_site(&sites, "src/b.rs", 12), Some(&sites[1]));
+        assert_eq!(locate_site(&sites, "src/b.rs", 3), None);
+        assert_eq!(locate_site(&[], "src/a.rs", 1), None);
+    }
+}
@@ -6,40 +6,69 @@
 // syn = { version = "1.0", features = ["full"] }
 // quote = "1.0"
 
-use core::panic;
-use std::env;
+mod ast_cache;
+mod checksum;
+mod cli;
+mod crate_names;
+mod crate_source;
+mod def_path;
+mod failure;
+mod filter;
+mod function_index;
+mod input;
+mod journal;
+mod locator;
+mod module_tree;
+mod path_rewrite;
+mod recovery;
+mod report;
+mod retry;
+mod safe_unpack;
+mod scan;
+mod unpack_cache;
+mod vfs;
+
 use std::fs;
 use std::fs::OpenOptions;
-use std::fs::ReadDir;
-use std::io::BufWriter;
 use std::path::{Path, PathBuf};
 use std::io::Write;
-use std::panic::{catch_unwind, UnwindSafe};
 
-use csv::{ReaderBuilder, StringRecord, WriterBuilder};
 use serde::Serialize;
-use syn::token::Impl;
-use syn::ForeignItem;
 use syn::ForeignItemFn;
-use syn::ForeignItemMacro;
-use syn::ImplItemMacro;
 use syn::ImplItemMethod;
 use syn::ItemMacro;
 use syn::ItemMacro2;
-use syn::TraitItemMacro;
 use syn::TraitItemMethod;
-use syn::{File, Item, ItemFn, spanned::Spanned};
+use syn::{ItemFn, spanned::Spanned};
 
-use walkdir::WalkDir;
-use flate2::read::GzDecoder;
-use tar::Archive;
 use anyhow::{Context, Result};
+use clap::Parser;
+
+use ast_cache::{AstCache, ParsedSource};
+use cli::{Cli, Command, ExtractArgs};
+use crate_source::{CrateCache, CrateLocation, LocatedCrate, OpenedCrate};
+use filter::FilterExpr;
+use input::{FunctionRecord, InputFormat, InputRow, RecordSource};
+use failure::{Failure, FailureKind, FailureLocation, FailureSink};
+use journal::Journal;
+use locator::FunctionLocator;
+use path_rewrite::PathRewriter;
+use recovery::UnparsedLog;
+use safe_unpack::record_rejections;
 
 /// 用于保存目标函数的注释状态及内容
 #[derive(Debug, Serialize)]
 struct FunctionCommentStatus {
     crate_name:String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crate_version: Option<String>,
+    /// 与 crate index 校验一致的 .crate SHA-256，只在指定 --crate-index 时存在
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crate_checksum: Option<String>,
     def_path: String,
+    /// 函数实际所在的模块路径，由 crate 的模块树得出；文件不在模块树中时没有
+    #[serde(skip_serializing_if = "Option::is_none")]
+    module_path: Option<String>,
     file: String,
     line: usize,
     has_doc: bool,
@@ -49,7 +78,7 @@ struct FunctionCommentStatus {
 }
 
 /// 使用 syn 提取函数中的文档注释（通过 #[doc = "..."] 属性）
-fn extract_doc_comments(func: &FunctionMacroType) -> Vec<String> {
+fn extract_doc_comments(func: &FunctionMacroType<'_>) -> Vec<String> {
     match func{
         FunctionMacroType::ItemFn(item_fn) => {
                                 item_fn.attrs
@@ -148,10 +177,9 @@ fn extract_doc_comments(func: &FunctionMacroType) -> Vec<String> {
 fn extract_comments_from_lines(lines: &[&str]) -> Vec<String> {
     //let mut res_before_comment=Vec::new();
     let mut comments = Vec::new();
-    let mut commentStack = Vec::new();         // 块注释嵌套计数器
+    let mut comment_stack = Vec::new();         // 块注释嵌套计数器
     let mut current_block = String::new(); // 当前正在收集的块注释内容
     //let mut inside_doc=0;
-    let mut i=0;
     for line in lines{
         //println!("before a line {:?}",comments);
         //let line=lines[i];
@@ -161,11 +189,11 @@ fn extract_comments_from_lines(lines: &[&str]) -> Vec<String> {
         while pos < chars.len() {
             //println!("now char: {}", chars[pos]);
             //println!("now current_block: {}", current_block);
-            if commentStack.is_empty() {
+            if comment_stack.is_empty() {
                 // 检查是否是单行注释
                 if pos + 1 < chars.len() && chars[pos] == '/' && chars[pos + 1] == '/' {
                     // 直接将本行后半部分作为单行注释
-                    if (pos + 2 < chars.len()&& chars[pos+2]!='/' && chars[pos+2]!='!')
+                    if pos + 2 < chars.len()&& chars[pos+2]!='/' && chars[pos+2]!='!'
                     {
                         //println!("before push {:?}",comments);
                         let comment: String = chars[pos..].iter().collect();
@@ -180,13 +208,13 @@ fn extract_comments_from_lines(lines: &[&str]) -> Vec<String> {
                 }
                 // 检查是否是块注释的起始标记 "/*"
                 else if pos + 1 < chars.len() && chars[pos] == '/' && chars[pos + 1] == '*' {
-                    if (pos + 2 < chars.len()&&chars[pos+1]!='*'&&chars[pos+2]!='!')
+                    if pos + 2 < chars.len()&&chars[pos+1]!='*'&&chars[pos+2]!='!'
                     {
-                        commentStack.push(commentType::inline);
+                        comment_stack.push(CommentType::Inline);
                         current_block.push_str("/*");
                         pos += 2;
                     }else{
-                        commentStack.push(commentType::doc);
+                        comment_stack.push(CommentType::Doc);
                         pos += 3;
                     }
                 } else {
@@ -195,22 +223,22 @@ fn extract_comments_from_lines(lines: &[&str]) -> Vec<String> {
             } else {
                 // 已经在块注释中，处理嵌套情况
                 if pos + 1 < chars.len() && chars[pos] == '/' && chars[pos + 1] == '*' {
-                    commentStack.push(commentType::inline);
-                    if let commentType::inline=commentStack[0]{
+                    comment_stack.push(CommentType::Inline);
+                    if let CommentType::Inline=comment_stack[0]{
                         current_block.push_str("/*");
                     }
                     pos += 2;
                 } else if pos + 1 < chars.len() && chars[pos] == '*' && chars[pos + 1] == '/' {
-                    match commentStack[0]{
-                        commentType::doc => {
-                            let comment_pop=commentStack.pop();
+                    match comment_stack[0]{
+                        CommentType::Doc => {
+                            comment_stack.pop();
                             pos += 2;
                         },
-                        commentType::inline => {
-                            let comment_pop=commentStack.pop();
+                        CommentType::Inline => {
+                            comment_stack.pop();
                             current_block.push_str("*/");
                             pos += 2;
-                            if commentStack.is_empty() {
+                            if comment_stack.is_empty() {
                                 // 块注释结束，将收集到的块注释保存
                                 comments.push(current_block.trim().to_string());
                                 current_block.clear();
@@ -218,7 +246,7 @@ fn extract_comments_from_lines(lines: &[&str]) -> Vec<String> {
                         },
                     }
                 } else {
-                    if let commentType::inline=commentStack[0]{
+                    if let CommentType::Inline=comment_stack[0]{
                         current_block.push(chars[pos]);
                     }
                     pos += 1;
@@ -228,10 +256,9 @@ fn extract_comments_from_lines(lines: &[&str]) -> Vec<String> {
         //println!("after a line {:?}",comments);
         //println!("after a line current_block{:?}",current_block);
         // 如果本行结束后仍处于块注释中，则换行继续累积内容
-        if !commentStack.is_empty() {
+        if !comment_stack.is_empty() {
             current_block.push('\n');
         }
-        i+=1;
     }
     
     // 如果块注释没有正确闭合，仍将当前内容保存
@@ -245,9 +272,9 @@ fn extract_comments_from_lines(lines: &[&str]) -> Vec<String> {
 /// 提取指定范围内的注释，包括函数定义前的注释和函数体内的注释。
 /// - extracted_start_line: 目标函数起始行号（1-indexed）
 /// - extracted_end_line: 目标函数结束行号（1-indexed）
-enum commentType{
-    doc,
-    inline,
+enum CommentType{
+    Doc,
+    Inline,
 }
 fn extract_inline_comments(source: &str, extracted_start_line: usize, extracted_end_line: usize) -> Vec<String> {
     let lines: Vec<&str> = source.lines().collect();
@@ -255,7 +282,7 @@ fn extract_inline_comments(source: &str, extracted_start_line: usize, extracted_
 
     //let mut res_before_comment=Vec::new();
     let mut comments = Vec::new();
-    let mut commentStack = Vec::new();         // 块注释嵌套计数器
+    let mut comment_stack = Vec::new();         // 块注释嵌套计数器
     let mut current_block = String::new(); // 当前正在收集的块注释内容
     //let mut inside_doc=0;
     let mut i=0;
@@ -268,11 +295,11 @@ fn extract_inline_comments(source: &str, extracted_start_line: usize, extracted_
         while pos < chars.len() {
             //println!("now char: {}", chars[pos]);
             //println!("now current_block: {}", current_block);
-            if commentStack.is_empty() {
+            if comment_stack.is_empty() {
                 // 检查是否是单行注释
                 if pos + 1 < chars.len() && chars[pos] == '/' && chars[pos + 1] == '/' {
                     // 直接将本行后半部分作为单行注释
-                    if (pos + 2 < chars.len()&& chars[pos+2]!='/' && chars[pos+2]!='!')
+                    if pos + 2 < chars.len()&& chars[pos+2]!='/' && chars[pos+2]!='!'
                     {
                         //println!("before push {:?}",comments);
                         let comment: String = chars[pos..].iter().collect();
@@ -287,17 +314,17 @@ fn extract_inline_comments(source: &str, extracted_start_line: usize, extracted_
                 }
                 // 检查是否是块注释的起始标记 "/*"
                 else if pos + 1 < chars.len() && chars[pos] == '/' && chars[pos + 1] == '*' {
-                    if (pos + 2 < chars.len()&&chars[pos+1]!='*'&&chars[pos+2]!='!')
+                    if pos + 2 < chars.len()&&chars[pos+1]!='*'&&chars[pos+2]!='!'
                     {
-                        commentStack.push(commentType::inline);
+                        comment_stack.push(CommentType::Inline);
                         current_block.push_str("/*");
                         pos += 2;
                     }else{
-                        commentStack.push(commentType::doc);
+                        comment_stack.push(CommentType::Doc);
                         pos += 3;
                     }
                 } else {
-                    if (!comments.is_empty()&&chars[pos]!=' '){
+                    if !comments.is_empty()&&chars[pos]!=' '{
                         comments.clear();
                     }
                     pos += 1;
@@ -305,22 +332,22 @@ fn extract_inline_comments(source: &str, extracted_start_line: usize, extracted_
             } else {
                 // 已经在块注释中，处理嵌套情况
                 if pos + 1 < chars.len() && chars[pos] == '/' && chars[pos + 1] == '*' {
-                    commentStack.push(commentType::inline);
-                    if let commentType::inline=commentStack[0]{
+                    comment_stack.push(CommentType::Inline);
+                    if let CommentType::Inline=comment_stack[0]{
                         current_block.push_str("/*");
                     }
                     pos += 2;
                 } else if pos + 1 < chars.len() && chars[pos] == '*' && chars[pos + 1] == '/' {
-                    match commentStack[0]{
-                        commentType::doc => {
-                            let comment_pop=commentStack.pop();
+                    match comment_stack[0]{
+                        CommentType::Doc => {
+                            comment_stack.pop();
                             pos += 2;
                         },
-                        commentType::inline => {
-                            let comment_pop=commentStack.pop();
+                        CommentType::Inline => {
+                            comment_stack.pop();
                             current_block.push_str("*/");
                             pos += 2;
-                            if commentStack.is_empty() {
+                            if comment_stack.is_empty() {
                                 // 块注释结束，将收集到的块注释保存
                                 comments.push(current_block.trim().to_string());
                                 current_block.clear();
@@ -328,7 +355,7 @@ fn extract_inline_comments(source: &str, extracted_start_line: usize, extracted_
                         },
                     }
                 } else {
-                    if let commentType::inline=commentStack[0]{
+                    if let CommentType::Inline=comment_stack[0]{
                         current_block.push(chars[pos]);
                     }
                     pos += 1;
@@ -338,7 +365,7 @@ fn extract_inline_comments(source: &str, extracted_start_line: usize, extracted_
         //println!("after a line {:?}",comments);
         //println!("after a line current_block{:?}",current_block);
         // 如果本行结束后仍处于块注释中，则换行继续累积内容
-        if !commentStack.is_empty() {
+        if !comment_stack.is_empty() {
             current_block.push('\n');
         }
         i+=1;
@@ -354,7 +381,7 @@ fn extract_inline_comments(source: &str, extracted_start_line: usize, extracted_
     // 2. 提取函数体内部的注释（从 extracted_start_line 到 extracted_end_line 行）
     if extracted_start_line - 1 < lines.len() && extracted_end_line <= lines.len() {
         //println!("start extract inline:{:?}",result);
-        let inside_lines: Vec<&str> = lines[extracted_start_line - 1 .. extracted_end_line].iter().cloned().collect();
+        let inside_lines: Vec<&str> = lines[extracted_start_line - 1 .. extracted_end_line].to_vec();
         let inside_comments = extract_comments_from_lines(&inside_lines);
         //println!("after extract inline commet:{:?}",inside_comments);
         result.extend(inside_comments);
@@ -364,222 +391,70 @@ fn extract_inline_comments(source: &str, extracted_start_line: usize, extracted_
     result
 }
 
-enum FunctionMacroType {
-    ItemFn(ItemFn),
-    ForeignItemFn(ForeignItemFn),
-    ImplItemMethod(ImplItemMethod),
-    ItemMacro(ItemMacro),
-    ItemMacro2(ItemMacro2),
+enum FunctionMacroType<'ast> {
+    ItemFn(&'ast ItemFn),
+    ForeignItemFn(&'ast ForeignItemFn),
+    ImplItemMethod(&'ast ImplItemMethod),
+    ItemMacro(&'ast ItemMacro),
+    ItemMacro2(&'ast ItemMacro2),
     //ForeignItemMacro(ForeignItemMacro),
     //ImplItemMacro(ImplItemMacro),
-    TraitItemMethod(TraitItemMethod),
+    TraitItemMethod(&'ast TraitItemMethod),
 }
 
-fn find_foreign_function (item:&ForeignItem,target_line: usize)-> Option<FunctionMacroType>{
-    match item{
-        ForeignItem::Fn(foreign_item_fn) => {
-            let start_line = foreign_item_fn.span().start().line;
-            let end_line=foreign_item_fn.span().end().line;
-            if start_line <= target_line && end_line >=target_line  
-            {
-                return Some(FunctionMacroType::ForeignItemFn(foreign_item_fn.clone()));
-            }else{
-                return None;
-            }
-        },
-        //ForeignItem::Static(foreign_item_static) => todo!(),
-        //ForeignItem::Type(foreign_item_type) => todo!(),
-        /*ForeignItem::Macro(foreign_item_macro) => {
-            let start_line = foreign_item_macro.span().start().line;
-            let end_line=foreign_item_macro.span().end().line;
-            if start_line <= target_line && end_line >=target_line  
-            {
-                return Some(FunctionMacroType::ForeignItemMacro(foreign_item_macro.clone()));
-            }else{
-                return None;
-            }
-        },*/
-        //ForeignItem::Verbatim(token_stream) => todo!(),
-        _ => {return None;},
-    }
-}
-
-fn find_function_item(item:&Item,target_line: usize) ->Option<FunctionMacroType>{
-    match item{
-        //Item::Const(item_const) => {return None;},
-        //Item::Enum(item_enum) => {},
-        //Item::ExternCrate(item_extern_crate) => {},
-        Item::Fn(item_fn) => {
-            let start_line = item_fn.span().start().line;
-            let end_line=item_fn.span().end().line;
-            if start_line <= target_line && end_line >=target_line  
-            {
-                return Some(FunctionMacroType::ItemFn(item_fn.clone()));
-            }else{
-                return None;
-            }
-        },
-        Item::ForeignMod(item_foreign_mod) => {
-            for foreign_item in &item_foreign_mod.items{
-                match foreign_item{
-                    ForeignItem::Fn(foreign_item_fn) => {
-                        let start_line = foreign_item_fn.span().start().line;
-                        let end_line=foreign_item_fn.span().end().line;
-                        if start_line <= target_line && end_line >=target_line  
-                        {
-                            return Some(FunctionMacroType::ForeignItemFn(foreign_item_fn.clone()));
-                        }else{
-                            return None;
-                        }
-                    },
-                    //ForeignItem::Static(foreign_item_static) => todo!(),
-                    //ForeignItem::Type(foreign_item_type) => todo!(),
-                    /*ForeignItem::Macro(foreign_item_macro) => {
-                        let start_line = foreign_item_macro.span().start().line;
-                        let end_line=foreign_item_macro.span().end().line;
-                        if start_line <= target_line && end_line >=target_line  
-                        {
-                            return Some(FunctionMacroType::ForeignItemMacro(foreign_item_macro.clone()));
-                        }else{
-                            return None;
-                        }
-                    },*/
-                    ///ForeignItem::Verbatim(token_stream) => todo!(),
-                    _ => {},
-                }
-            }
-            return None;
-        },
-        Item::Impl(item_impl) =>{
-            for impl_item in &item_impl.items{
-                match impl_item{
-                    syn::ImplItem::Const(impl_item_const) => {
-                    },
-                    syn::ImplItem::Method(impl_item_method) => {
-                        let start_line = impl_item_method.span().start().line;
-                        let end_line=impl_item_method.span().end().line;
-                        if start_line <= target_line && end_line >=target_line  
-                        {
-                            return Some(FunctionMacroType::ImplItemMethod(impl_item_method.clone()))
-                        }
-                    },
-                    syn::ImplItem::Type(impl_item_type) => {},
-                    syn::ImplItem::Macro(impl_item_macro) => {
-                        /*let start_line = impl_item_macro.span().start().line;
-                        let end_line=impl_item_macro.span().end().line;
-                        if start_line <= target_line && end_line >=target_line  
-                        {
-                            return Some(FunctionMacroType::ImplItemMacro(impl_item_macro.clone()))
-                        }*/
-                    },
-                    syn::ImplItem::Verbatim(token_stream) => {},
-                    _ => {},
-                }
-            }
-            return None;
-        },
-        Item::Macro(item_macro) => {
-            let start_line = item_macro.span().start().line;
-            let end_line=item_macro.span().end().line;
-            if start_line <= target_line && end_line >=target_line  
-            {
-                return Some(FunctionMacroType::ItemMacro(item_macro.clone()));
-            };
-            return None;
-        },
-        Item::Macro2(item_macro2) => {
-            let start_line = item_macro2.span().start().line;
-            let end_line=item_macro2.span().end().line;
-            if start_line <= target_line && end_line >=target_line  
-            {
-                return Some(FunctionMacroType::ItemMacro2(item_macro2.clone()));
-            };
-            return None;
-        },
-        Item::Mod(item_mod) => {
-            let mod_start_line=item_mod.span().start().line;
-            let mod_end_line=item_mod.span().end().line;
-            if mod_start_line <= target_line && mod_end_line >=target_line  
-            {
-                match &item_mod.content{
-                    Some((_,mod_items)) => {
-                        for mod_item in mod_items{
-                            match find_function_item(mod_item, target_line){
-                                Some(res) =>{return Some(res)},
-                                None => {},
-                            }
-                        }
-                        return None;
-                    },
-                    None => {return None;},
-                }
-            }
-            else{
-                return None;
-            }
-        },
-        //Item::Static(item_static) => {},
-        //Item::Struct(item_struct) => {},
-        Item::Trait(item_trait) => {
-            for traititem in &item_trait.items{
-                match traititem{
-                    syn::TraitItem::Const(trait_item_const) =>{
-
-                    },
-                    syn::TraitItem::Method(trait_item_method) => {
-                        let start_line = trait_item_method.span().start().line;
-                        let end_line=trait_item_method.span().end().line;
-                        if start_line <= target_line && end_line >=target_line  
-                        {
-                            return Some(FunctionMacroType::TraitItemMethod(trait_item_method.clone()));
-                        };
-                        //return None;
-                    },
-                    syn::TraitItem::Type(trait_item_type) => {},
-                    syn::TraitItem::Macro(trait_item_macro) => {},
-                    syn::TraitItem::Verbatim(token_stream) => {},
-                    _ => {},
-                }
-                //return None;
-            }
-            return None;
-        },
-        //Item::TraitAlias(item_trait_alias) => {},
-        //Item::Type(item_type) => {},
-        //Item::Union(item_union) => {},
-        //Item::Use(item_use) => {},
-        //Item::Verbatim(token_stream) => {},
-        _ =>{return None;},
+impl FunctionMacroType<'_> {
+    /// 函数（或宏）名以及它在源文件中的起止行号（1-indexed）
+    fn name_and_lines(&self) -> (String, usize, usize) {
+        match self{
+            FunctionMacroType::ItemFn(item_fn) => {
+                (item_fn.sig.ident.to_string(), item_fn.span().start().line, item_fn.span().end().line)
+            },
+            FunctionMacroType::ForeignItemFn(foreign_item_fn) => {
+                (foreign_item_fn.sig.ident.to_string(), foreign_item_fn.span().start().line, foreign_item_fn.span().end().line)
+            },
+            FunctionMacroType::ImplItemMethod(impl_item_method) => {
+                (impl_item_method.sig.ident.to_string(), impl_item_method.span().start().line, impl_item_method.span().end().line)
+            },
+            FunctionMacroType::ItemMacro(item_macro) => {
+                (item_macro.ident.clone().map(|ident| ident.to_string()).unwrap_or_default(), item_macro.span().start().line, item_macro.span().end().line)
+            },
+            FunctionMacroType::ItemMacro2(item_macro2) =>{
+                (item_macro2.ident.to_string(), item_macro2.span().start().line, item_macro2.span().end().line)
+            },
+            FunctionMacroType::TraitItemMethod(trait_item_method) => {
+                (trait_item_method.sig.ident.to_string(), trait_item_method.span().start().line, trait_item_method.span().end().line)
+            },
+        }
     }
 }
 
-/// 在 AST 中查找起始行号匹配的函数
-fn find_function_by_start_line(ast: &File, target_line: usize) -> Option<FunctionMacroType> {
-    /*  for item in items {
-        match item {
-            Item::Mod(module) => {
-                println!("Found module: {}", module.ident);
-                if let Some((_, items)) = &module.content {
-                    visit_items(items);
-                }
-            }
-            Item::Fn(function) => {
-                println!("Found function: {}", function.sig.ident);
-            }
-            _ => {}
-        }
-    } */
-    for item in &ast.items {
-        match find_function_item(item, target_line){
-            Some(res) => return Some(res),
-            None => {},
-        }
+/// 提取一个已定位函数的文档注释与普通注释
+fn function_comment_status(crate_name: &str, crate_version: Option<&str>, def_path: String, file: String, source: &str, func: &FunctionMacroType<'_>) -> FunctionCommentStatus {
+    let (_fn_name, extracted_start_line, extracted_end_line) = func.name_and_lines();
+    let doc_comments = extract_doc_comments(func);
+    let has_doc = !doc_comments.is_empty();
+    let doc_paragraph = doc_comments.join(" ");
+
+    // 使用文本扫描提取普通注释（基于函数的起止行）
+    let inline_comments = extract_inline_comments(source, extracted_start_line,extracted_end_line);
+    let has_inline_comment = !inline_comments.is_empty();
+    let inline_comment_paragraph = inline_comments.join(" ");
+    FunctionCommentStatus {
+        crate_name: crate_name.to_owned(),
+        crate_version: crate_version.map(|version| version.to_owned()),
+        crate_checksum: None,
+        def_path,
+        module_path: None,
+        file,
+        line: extracted_start_line,
+        has_doc,
+        doc_paragraph,
+        has_inline_comment,
+        inline_comment_paragraph,
     }
-    return None;
 }
 
 use serde::Deserialize;
-use std::collections::HashMap;
 
 #[derive(Debug, Deserialize)]
 struct Root {
@@ -601,37 +476,62 @@ struct Package {
     version: String,
 }
 
-fn write_when_fail(fail_result_root:&PathBuf, record:&StringRecord){
-    let failed_file = OpenOptions::new()
-    .create(true)    // 不存在就创建
-    .append(true)    // 以追加模式，不会截断
-    .open(&fail_result_root).expect("failed to open or create records_failed_to_extract.csv");
-    let buf = BufWriter::new(failed_file);
-    // 5. 使用 csv::Writer 从该 writer 写入单行
-    let mut wtr = WriterBuilder::new()
-        .has_headers(false)  // 不写入任何 header
-        .from_writer(buf);
-    // 6. 写入当前这条 record，并刷新
-    wtr.write_record(record).expect("failed to write into bufwriter");
-    wtr.flush().expect("failed to flush bufwriter");
+/// 将一个 crate 的结果以 JSON 数组追加到 result-<crate>.json，返回输出路径
+fn write_crate_results(result_root: &Path, crate_name: &str, results: &[FunctionCommentStatus]) -> Result<PathBuf> {
+    let output_file_name="result-".to_owned()+crate_name+".json";
+    let output_path = result_root.join(output_file_name);
+    let json = serde_json::to_string_pretty(results)
+        .context("Failed to serialize to JSON")?;
+
+    let mut result_file = OpenOptions::new()
+        .create(true)   // 文件不存在时创建
+        .append(true)   // 每次写入都追加到末尾，而不截断
+        .open(&output_path)
+        .with_context(|| format!("failed to open or create {:?}", output_path))?;
+
+    // 将 JSON 文本及换行写入文件末尾
+    result_file.write_all(json.as_bytes())
+        .with_context(|| format!("Failed to append to {:?}", output_path))?;
+    result_file.write_all(b"\n")
+        .with_context(|| format!("Failed to append newline to {:?}", output_path))?;
+    Ok(output_path)
 }
-fn main() {
-    // 程序参数:
-    // args[1]:CSV 文件路径（记录中包含目标函数信息）
-    // args[2]:cache root
-    // args[3]:resul folder
-    let args: Vec<String> = env::args().collect();
-    println!("num:{}",args.len());
-    if args.len() < 4 {
-        eprintln!("Usage: {} <functions_csv> <crate_list_directory> <crates_cache_root> <result_directory>", args[0]);
-        std::process::exit(1);
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    match cli.command {
+        Command::Extract(args) => run_extract(&args),
+        Command::Scan(args) => scan::run_scan(&args),
+        Command::Local(args) => scan::run_local(&args),
+        Command::Report(args) => report::run_report(&args),
+        Command::Retry(args) => retry::run_retry(&args),
     }
-    let csv_path = Path::new(&args[1]);
-    //let crate_list = Path::new(&args[2]);
-    let cache_root=Path::new(&args[2]);
-    let result_root=Path::new(&args[3]);
-    let  fail_result_root=result_root.join("records_failed_to_extract.csv");
-    let fail_reason_path=result_root.join("records_failed_reason.txt");
+}
+
+/// 合并 --safety 与 --filter；两者都未指定时只处理 Safe 函数
+fn build_record_filter(args: &ExtractArgs) -> Result<FilterExpr> {
+    let safety_filter = if !args.safety.is_empty() {
+        Some(FilterExpr::safety_in(&args.safety))
+    } else if args.filter.is_none() {
+        Some(FilterExpr::safety_in(&["Safe".to_owned()]))
+    } else {
+        None
+    };
+    let expr_filter = args.filter.as_deref().map(FilterExpr::parse).transpose()?;
+    Ok(match (safety_filter, expr_filter) {
+        (Some(safety_filter), Some(expr_filter)) => safety_filter.and(expr_filter),
+        (Some(filter), None) | (None, Some(filter)) => filter,
+        (None, None) => unreachable!("safety filter defaults to Safe when no filter is given"),
+    })
+}
+
+/// extract 子命令：按函数 CSV 中的记录逐条定位函数并提取注释
+fn run_extract(args: &ExtractArgs) -> Result<()> {
+    let input_path = args.functions.as_path();
+    let mut crate_cache = CrateCache::new(&args.cache)?;
+    let result_root = args.result_dir.as_path();
+    fs::create_dir_all(result_root)
+        .with_context(|| format!("cannot create result directory {:?}", result_root))?;
     //let crate_list_data = fs::read_to_string(crate_list).expect("cannot read crate_list file");
     // 2. 反序列化到 Root
     //let crate_list_root: Root = serde_json::from_str(&crate_list_data).expect("cannot deserialize crate list");
@@ -642,93 +542,139 @@ fn main() {
         //crate_list_map.insert(entry.package.name.clone(), entry.package);
     //}
 
-    let mut rdr = ReaderBuilder::new()
-        .has_headers(false)
-        .from_path(csv_path)
-        .unwrap_or_else(|e| panic!("Unable to read CSV file: {}", e));
+    let input_format = args.input_format.unwrap_or_else(|| InputFormat::detect(input_path));
+    let input = RecordSource::open(input_path, input_format, args.headers, args.column_map.as_deref())?;
+    let record_filter = build_record_filter(args)?;
+    let path_rewriter = PathRewriter::load(args.path_rewrite.as_deref())?;
+    let mut failures = FailureSink::open(result_root, input.headers())?;
+    let mut unparsed_log = UnparsedLog::new(result_root);
+    let mut ast_cache = AstCache::default();
+    // 当前 crate 的模块树与 def_path 索引，第一次查找函数时建立
+    let mut locator = FunctionLocator::new(args.locate_by);
+    let mut located_by_def_path = 0;
+    // def_path 与函数实际所在模块不一致的记录数
+    let mut def_path_mismatches = 0;
+
+    let mut journal = Journal::open(result_root, args.resume)?;
+    if args.resume {
+        println!(
+            "resume: {} records and {} crates already finished",
+            journal.done_item_count(),
+            journal.done_crate_count()
+        );
+    }
 
     let mut results = Vec::new();
-    // 解析 CSV 记录，假定格式为：
-    // - 第3列（索引2）：def_path
-    // - 第9列（索引8）：文件相对路径
-    // - 第10列（索引9）：函数起始行号（1-indexed）
-    println!("start extract csv!");
+    // 与 results 一一对应，结果落盘后才写入日志
+    let mut pending_item_ids: Vec<String> = Vec::new();
+    // 逐条解析输入记录，各字段所在的列由列映射决定
+    println!("start extract records!");
     let mut crate_name=String::new();
+    let mut crate_version: Option<String> = None;
+    // 由 git checkout 或本地目录提供源码时的根目录
+    let mut source_root: Option<PathBuf> = None;
     //let mut crate_found_flag=true;
-    let mut crate_root=String::new();
+    let mut opened_crate: Option<OpenedCrate> = None;
+    // 当前 crate 定位或打开失败的原因
+    let mut crate_open_failure: Option<Failure> = None;
     //let mut crate_name_path_map:HashMap<String, String> = HashMap::new();
     let mut all_extracted_function_num=0;
-    let mut failed_extract_record_count=0;
     let mut extract_index=0;
     let mut allhandlecount=0;
     let mut hasdocrecord=0;
     let mut hascommentcount=0;
     let mut has_doc_or_comment=0;
-    for result in rdr.records() {
+    for result in input {
         extract_index+=1;
         println!("extract_index: {}",&extract_index);
-        if (extract_index<33348){
+        if extract_index <= args.start_offset {
             continue;
         }
         println!("all handled{} has doc{} has inline comment{} has doc or comment {}",allhandlecount,hasdocrecord,hascommentcount,has_doc_or_comment);
-        let record = result.expect("Error reading CSV record");
-        if record.len() < 10 {
-            continue;
-        }
-        let mut new_crate_name=record.get(1).unwrap().to_string();
-        let function_safety=record.get(12).unwrap();
-        let item_id=record.get(0).unwrap().to_string();
-        let def_path = record.get(3).unwrap().to_string();
-        let mut rel_file = record.get(9).unwrap().to_string();
-        let start_line: usize = record.get(10).unwrap().parse().unwrap_or_else(|e| {
-            panic!("Failed to parse start line: {}", e)
-        });
-        if rel_file.starts_with("/opt/rustwide/cargo-home/registry/src/index.crates.io-6f17d22bba15001f"){
-            let rel_file_p = Path::new(&rel_file);
-            // 迭代组件，跳过 registry_root 及其后的一个组件
-            let mut iter = rel_file_p.iter()
-                // 跳过所有在 registry_root 之前的组件
-                .skip_while(|c| *c!= std::ffi::OsStr::new("index.crates.io-6f17d22bba15001f"))
-                // 跳过 registry_root 本身
-                .skip(1)
-                // 再跳过那个 crate 目录
-                .skip(1);
-            // 收集余下部分
-            let new_rel_file_p: PathBuf = iter.collect();
-            match new_rel_file_p.to_str(){
-                Some(new_rel_file_string) => {rel_file=new_rel_file_string.to_owned()},
-                None =>{
-                    let failed_reason_file = OpenOptions::new()
-                        .create(true)    // 不存在就创建
-                        .append(true)    // 以追加模式，不会截断
-                        .open(&fail_reason_path).expect("failed to open or create records_failed_to_extract.csv");
-                    let mut failed_reason_buf = BufWriter::new(failed_reason_file);
-                    let failed_reason_string=format!(
-                        "new relfile is empty informantion: {} {} failed_extract_record_count {}",
-                        &new_crate_name,
-                        &rel_file,
-                        &failed_extract_record_count
-                    );
-                    failed_reason_buf.write_all(failed_reason_string.as_bytes())
-                        .expect("failed to write string to file");
-                    failed_reason_buf.write_all(b"\n")
-                        .expect("failed to write newline");
-                    failed_reason_buf.flush().expect("failed to flush buffer");
-                    write_when_fail(&fail_result_root, &record);
-                    failed_extract_record_count+=1;
-                    println!("failed_extract_record_count: {}",&failed_extract_record_count);
+        let InputRow { raw: record, item_id: raw_item_id, record: parsed_record } = match result {
+            Ok(row) => row,
+            Err(e) => {
+                eprintln!("Error reading input record {}: {:#}", extract_index, e);
+                continue;
+            }
+        };
+        let function_record = match parsed_record {
+            Ok(function_record) => function_record,
+            Err(e) => {
+                if raw_item_id.as_deref().is_some_and(|item_id| journal.is_item_done(item_id)) {
                     continue;
-                },
+                }
+                failures.record(
+                    Some(&record),
+                    raw_item_id.as_deref(),
+                    &Failure::new(FailureKind::InvalidRecord, format!("invalid record {}: {:#}", extract_index, e)),
+                )?;
+                if let Some(item_id) = &raw_item_id {
+                    journal.mark_item_done(item_id)?;
+                }
+                continue;
             }
+        };
+        if journal.is_item_done(&function_record.item_id) {
+            continue;
+        }
+        // 过滤阶段：不满足过滤条件的记录不进入提取
+        if !record_filter.matches(&function_record) {
+            continue;
         }
+        let FunctionRecord {
+            item_id,
+            crate_name: mut new_crate_name,
+            version: new_crate_version,
+            def_path,
+            file: original_file,
+            start_line,
+            ..
+        } = function_record;
+        let mut location = FailureLocation {
+            crate_name: Some(new_crate_name.clone()),
+            crate_version: new_crate_version.clone(),
+            def_path: Some(def_path.clone()),
+            file: Some(original_file.clone()),
+            line: Some(start_line),
+            column: None,
+        };
+        // git checkout 或本地目录认领的路径直接从对应目录读取，其余路径按改写规则转成 .crate 内相对路径
+        let source_route = crate_cache.route(&original_file);
+        let new_source_root = source_route.as_ref().map(|route| route.root.clone());
+        let rel_file = match source_route {
+            Some(route) => route.rel_file,
+            None => path_rewriter.rewrite(&original_file),
+        };
+        if let Some(error) = path_rewrite::rel_file_error(&rel_file) {
+            let failure = Failure::new(FailureKind::PathRewrite, error).at(&location);
+            failures.record(Some(&record), Some(&item_id), &failure)?;
+            journal.mark_item_done(&item_id)?;
+            continue;
+        }
+        location.file = Some(rel_file.clone());
         //println!("{}",function_safety);
         println!("now function: {:?}", &record);
         println!("now function: {} {} {} {} {}", &item_id,&new_crate_name,&def_path,&rel_file,&start_line);
-        if (!function_safety.eq("Safe")){
+        // 经过名字索引统一 crate 名的写法，同一个 crate 的不同写法不会触发重新打开
+        if new_source_root.is_none() {
+            match crate_cache.resolve_name(&new_crate_name) {
+                Ok(resolved_crate_name) => new_crate_name = resolved_crate_name,
+                Err(e) => {
+                    all_extracted_function_num+=1;
+                    let failure = Failure::new(FailureKind::CrateMissing, format!("{:#}", e)).at(&location);
+                    failures.record(Some(&record), Some(&item_id), &failure)?;
+                    journal.mark_item_done(&item_id)?;
+                    continue;
+                },
+            }
+        }
+        // 续跑时跳过上次已经完成的 crate，不再重新打开
+        if journal.is_crate_done(&new_crate_name, extract_index) {
             continue;
         }
         all_extracted_function_num+=1;
-        if !new_crate_name.eq(&crate_name){
+        if !new_crate_name.eq(&crate_name) || new_crate_version != crate_version || new_source_root != source_root {
 
             //let new_package=crate_list_map.get(&new_crate_name);
             //match new_package{
@@ -741,314 +687,121 @@ fn main() {
             //}
             //let crate_file_name=package_name+"-"+package_version;
 
-            if (!results.is_empty()){
-                let output_file_name="result-".to_owned()+&crate_name.clone()+".json";
-                let output_path = result_root.join(output_file_name);
-                let json = serde_json::to_string_pretty(&results)
-                    .expect("Failed to serialize to JSON");
-            
-                let mut result_file = OpenOptions::new()
-                    .create(true)   // 文件不存在时创建
-                    .append(true)   // 每次写入都追加到末尾，而不截断
-                    .open(&output_path).expect("failed to open or create result.json");
-            
-                // 将 JSON 文本及换行写入文件末尾
-                if let Err(e) = result_file.write_all(json.as_bytes()) {
-                    eprintln!("Failed to append to {:?}: {}", output_path, e);
-                    return;
-                }
-                if let Err(e) = result_file.write_all(b"\n") {
-                    eprintln!("Failed to append newline to {:?}: {}", output_path, e);
-                    return;
-                }
+            if !results.is_empty(){
+                let output_path = write_crate_results(result_root, &crate_name, &results)?;
                 results.clear();
+                for done_item_id in pending_item_ids.drain(..) {
+                    journal.mark_item_done(&done_item_id)?;
+                }
 
                 println!("Results written of {} to {:?}", crate_name,output_path); 
-
-                let now_crate_root_path=Path::new(&crate_root);
-                if now_crate_root_path.exists() {
-                    match fs::remove_dir_all(&now_crate_root_path){
-                        Ok(_) => {
-                            println!("has deleted {:?}", &now_crate_root_path);
-                        }
-                        Err(_) => {
-                            println!("failed to delete {:?}", &now_crate_root_path);
-                        },
-                    }
-                    
-                } else {
-                    println!("the dir does not exist {:?}", &now_crate_root_path);
-                }                
             }
+            // 切换 crate 时，之前属于旧 crate 的记录都已写入结果或失败文件
+            if !crate_name.is_empty() {
+                journal.mark_crate_done(&crate_name, extract_index - 1)?;
+            }
+            opened_crate = None;
+            ast_cache.clear();
+            locator.clear();
             //match crate_name_path_map.get(&crate_name){
                 //Some(crate_root_path) => {crate_root=crate_root_path.clone();},
                 //None =>{
-                    let mut target_crate_path=cache_root.join(&new_crate_name);
-                    let newcratename=new_crate_name.replace("_", "-");
-                    let target_crate_path2=cache_root.join(&newcratename);
-                    if !target_crate_path.exists() || !target_crate_path.is_dir() {
-                        //println!("crate name{:?} does not exit or is not a dir", &new_crate_name);
-                        new_crate_name=newcratename;
-                        target_crate_path=target_crate_path2.clone();
-                        if !target_crate_path2.exists() || !target_crate_path2.is_dir() {
-                            let failed_reason_file = OpenOptions::new()
-                            .create(true)    // 不存在就创建
-                            .append(true)    // 以追加模式，不会截断
-                            .open(&fail_reason_path).expect("failed to open or create records_failed_to_extract.csv");
-                            let mut failed_reason_buf = BufWriter::new(failed_reason_file);
-                            let failed_reason_string=format!(
-                                "cannot find crate_name target crate path informantion: {} {} failed_extract_record_count {}",
-                                &new_crate_name,
-                                &rel_file,
-                                &failed_extract_record_count
-                            );
-                            failed_reason_buf.write_all(failed_reason_string.as_bytes())
-                                .expect("failed to write string to file");
-                            failed_reason_buf.write_all(b"\n")
-                                .expect("failed to write newline");
-                            failed_reason_buf.flush().expect("failed to flush buffer");
-                            write_when_fail(&fail_result_root, &record);
-                            failed_extract_record_count+=1;
-                            println!("failed_extract_record_count: {}",&failed_extract_record_count);
-                            continue;
-                        } 
-                    }
-                    let mut zip_path: Option<PathBuf> = None;
-                    let mut target_crate_file_count=0;
-                    let read_target_crate_path_res = fs::read_dir(&target_crate_path);
-                    let entries = match read_target_crate_path_res {
-                        Ok(rd) => rd,
-                        Err(e) => {
-                            println!("cannot read dir {:?}: {}", target_crate_path, e);
-                            panic!("cannot read dir");
-                        }
-                    };  
-                    // 3. 寻找 .zip 并解压
-                    for entry_res in entries {
-                        let entry = match entry_res {
-                            Ok(en) => en,
-                            Err(e) => {
-                                println!("cannot read item in {:?} error: {}", target_crate_path, e);
-                                continue;
-                            }
-                        };
-
-                        let item_path = entry.path();
-                        if item_path.extension().and_then(|e| e.to_str()).map_or(false, |ext| ext.eq_ignore_ascii_case("crate")) 
-                        {
-                            zip_path = Some(item_path);
-                            break;
-                        }
-                    }
-                    let zip_crate_path = match zip_path{
-                        Some(p) => p,
-                        None => {
-                            println!("cannot find crate in {:?} ", target_crate_path);
-                            panic!("cannot find any crate")
-                        }
+                    let located = match &new_source_root {
+                        Some(root) => Ok(LocatedCrate {
+                            crate_name: new_crate_name.clone(),
+                            location: CrateLocation::Extracted(root.clone()),
+                        }),
+                        None => crate_cache.locate(&new_crate_name, new_crate_version.as_deref()),
                     };
-                
-                    // 3. 打开 .crate（实际上是 gzipped tarball）
-                    let zip_file_res = fs::File::open(&zip_crate_path);
-                    let zip_file = match zip_file_res {
-                        Ok(f) => f,
-                        Err(e) => {
-                            println!("cannot open file {:?}: {}", zip_crate_path, e);
-                            panic!("cannot open file")
-                        }
+                    let opened = match located {
+                        Ok(located) => {
+                            new_crate_name = located.crate_name.clone();
+                            crate_cache
+                                .open(&located)
+                                .map_err(|e| Failure::new(FailureKind::of_open_error(&e), format!("{:#}", e)))
+                        },
+                        Err(e) => Err(Failure::new(FailureKind::CrateMissing, format!("{:#}", e))),
                     };
-                
-                    // 4. 解压 GzDecoder -> tar Archive
-                    let decoder_res = GzDecoder::new(zip_file);
-                    // GzDecoder::new 直接返回，不会失败构造，但在读取时会报错
-                    let mut archive = Archive::new(decoder_res);
-                
-                    // 5. 提取所有条目到同一目录
-                    match archive.unpack(&target_crate_path) {
-                        Ok(()) => {
-                            println!("success unzip {:?} to {:?}", zip_crate_path, &target_crate_path);
-                        }
-                        Err(e) => {
-                            println!("failed to unzip {:?} : {}", zip_crate_path, e);
-                        }
+                    let crate_location = FailureLocation {
+                        crate_name: Some(new_crate_name.clone()),
+                        crate_version: new_crate_version.clone(),
+                        ..FailureLocation::default()
+                    };
+                    record_rejections(result_root, &mut failures, &crate_location, &crate_cache.take_rejections())?;
+                    // 失败时记下原因，这个 crate 的记录都按失败处理，之后照常处理下一个 crate
+                    match opened {
+                        Ok(opened) => {
+                            if let Some(failure) = opened.unverifiable_failure(&crate_location) {
+                                failures.record(None, None, &failure)?;
+                            }
+                            opened_crate=Some(opened);
+                            crate_open_failure=None;
+                        },
+                        Err(failure) => crate_open_failure=Some(failure),
                     }
-                    let folder_name = zip_crate_path
-                        .file_stem()                          // >>> "bitflags-2.9.0":contentReference[oaicite:2]{index=2}
-                        .and_then(|s| s.to_str())
-                        .unwrap_or_default();
-                    let extracted_file_dir = target_crate_path.join(folder_name);
-                    //println!("{:?}",&extracted_file_dir);
                     crate_name=new_crate_name;
-                    crate_root=extracted_file_dir.to_str().expect("failed tp convert extracted file path to string").to_owned();
+                    crate_version=new_crate_version;
+                    source_root=new_source_root;
                     //crate_name_path_map.insert(crate_name.clone(), crate_root.clone());
                 //}
             //}
         }
         //return 
-        let file_path: PathBuf = Path::new(&crate_root).join(&rel_file);
-        println!("extract: {} {} {:?}", def_path,&crate_root,&file_path);
-        if !file_path.exists(){
-            let failed_reason_file = OpenOptions::new()
-            .create(true)    // 不存在就创建
-            .append(true)    // 以追加模式，不会截断
-            .open(&fail_reason_path).expect("failed to open or create records_failed_to_extract.csv");
-            let mut failed_reason_buf = BufWriter::new(failed_reason_file);
-            let failed_reason_string=format!(
-                "file path does not exist information: {} {} {:?} \nfailed_extract_record_count {}",
-                &crate_name,
-                &rel_file,
-                &file_path,
-                &failed_extract_record_count
-            );
-            failed_reason_buf.write_all(failed_reason_string.as_bytes())
-                .expect("failed to write string to file");
-            failed_reason_buf.write_all(b"\n")
-                .expect("failed to write newline");
-            failed_reason_buf.flush().expect("failed to flush buffer");
-            write_when_fail(&fail_result_root, &record);
-            failed_extract_record_count+=1;
-            println!("failed_extract_record_count: {}",&failed_extract_record_count);
-            continue;
-        }
-        let source = fs::read_to_string(&file_path)
-            .unwrap_or_else(|e| panic!("Failed to read file {:?}: {}", file_path, e));
-
-        // 使用 syn 解析文件
-        // 使用 catch_unwind 包裹解析
-        let ast: File = match catch_unwind(|| syn::parse_str::<File>(&source)) {
-            // 闭包正常返回：可能是 Ok(ast) 或 Err(parse_error)
-            Ok(Ok(file)) => file,
-            Ok(Err(parse_err)) => {
-                let failed_reason_file = OpenOptions::new()
-                .create(true)    // 不存在就创建
-                .append(true)    // 以追加模式，不会截断
-                .open(&fail_reason_path).expect("failed to open or create records_failed_to_extract.csv");
-                let mut failed_reason_buf = BufWriter::new(failed_reason_file);
-                let failed_reason_string=format!(
-                    "Failed to parse file {:?}:{}\n {} {} \nfailed_extract_record_count {}",
-                    &file_path,
-                    &parse_err,
-                    &crate_name,
-                    &rel_file,
-                    &failed_extract_record_count
-                );
-                failed_reason_buf.write_all(failed_reason_string.as_bytes())
-                    .expect("failed to write string to file");
-                failed_reason_buf.write_all(b"\n")
-                    .expect("failed to write newline");
-                failed_reason_buf.flush().expect("failed to flush buffer");
-
-                write_when_fail(&fail_result_root, &record);
-                failed_extract_record_count += 1;
-                println!("failed_extract_record_count: {}", failed_extract_record_count);
-                continue;
-            }
-            Err(panic_payload) => {
-                // 尝试将 panic_payload 解构为 &str
-                let panic_reason = panic_payload
-                    .downcast_ref::<&str>()
-                    .map(|s| *s)
-                    // 如果不是 &str，再试试 String
-                    .or_else(|| panic_payload.downcast_ref::<String>().map(|s| s.as_str()))
-                    .unwrap_or("Unknown panic payload type");
-
-                let failed_reason_file = OpenOptions::new()
-                .create(true)    // 不存在就创建
-                .append(true)    // 以追加模式，不会截断
-                .open(&fail_reason_path).expect("failed to open or create records_failed_to_extract.csv");
-                let mut failed_reason_buf = BufWriter::new(failed_reason_file);
-                let failed_reason_string=format!(
-                    "Failed to parse file panic when parsing{:?} {}\n {} {} \nfailed_extract_record_count {}",
-                    &file_path,
-                    &panic_reason,
-                    &crate_name,
-                    &rel_file,
-                    &failed_extract_record_count
-                );
-                failed_reason_buf.write_all(failed_reason_string.as_bytes())
-                    .expect("failed to write string to file");
-                failed_reason_buf.write_all(b"\n")
-                    .expect("failed to write newline");
-                failed_reason_buf.flush().expect("failed to flush buffer");
-
-                write_when_fail(&fail_result_root, &record);
-                failed_extract_record_count += 1;
-                println!("failed_extract_record_count: {}", failed_extract_record_count);
+        location.crate_name = Some(crate_name.clone());
+        let crate_files = match &opened_crate {
+            Some(opened) => &opened.files,
+            None => {
+                let failure = crate_open_failure
+                    .clone()
+                    .unwrap_or_else(|| Failure::new(FailureKind::CrateOpen, "crate source is not opened"))
+                    .at(&location);
+                failures.record(Some(&record), Some(&item_id), &failure)?;
+                journal.mark_item_done(&item_id)?;
                 continue;
+            },
+        };
+        // 先按 def_path 在模块树中查找函数，找不到或有歧义时按记录的文件与行号查找
+        let def_path_sites = locator.def_path_sites(crate_files, &mut ast_cache, &def_path, &crate_name);
+        let (rel_file, start_line) = match locator::locate_site(&def_path_sites, &rel_file, start_line) {
+            Some(site) => {
+                located_by_def_path += 1;
+                let site_file = site.rel_file.to_string_lossy().into_owned();
+                if site_file != rel_file || !site.contains(Path::new(&rel_file), start_line) {
+                    println!("def_path {} found at {}:{} instead of {}:{}", def_path, site_file, site.start_line, rel_file, start_line);
+                }
+                (site_file, site.start_line)
             }
+            None => (rel_file, start_line),
         };
-
-
-        let ast: File = match syn::parse_str(&source) {
-            Ok(file) => file,
+        location.file = Some(rel_file.clone());
+        location.line = Some(start_line);
+        let file_path: PathBuf = crate_files.display_path(Path::new(&rel_file));
+        println!("extract: {} {:?} {:?}", def_path,crate_files.root(),&file_path);
+        if !crate_files.exists(Path::new(&rel_file)){
+            let failure = Failure::new(FailureKind::FileMissing, format!("file {:?} does not exist", &file_path)).at(&location);
+            failures.record(Some(&record), Some(&item_id), &failure)?;
+            journal.mark_item_done(&item_id)?;
+            continue;
+        }
+        // 使用 syn 解析文件，同一个 crate 中每个文件只读取、解析一次；整体解析失败（包括 panic）时逐个解析顶层条目，
+        // 能解析的条目照常匹配，无法解析的区域写入 unparsed_regions.jsonl
+        let ParsedSource { source, parsed, functions } = match ast_cache.get_or_parse(crate_files, Path::new(&rel_file)) {
+            Ok(parsed_source) => parsed_source,
             Err(e) => {
-                //println!("Failed to parse file {:?}: {}", file_path, e);
-                let failed_reason_file = OpenOptions::new()
-                .create(true)    // 不存在就创建
-                .append(true)    // 以追加模式，不会截断
-                .open(&fail_reason_path).expect("failed to open or create records_failed_to_extract.csv");
-                let mut failed_reason_buf = BufWriter::new(failed_reason_file);
-                let failed_reason_string=format!(
-                    "Failed to parse file {:?}:{}\n {} {} \nfailed_extract_record_count {}",
-                    &file_path,
-                    &e,
-                    &crate_name,
-                    &rel_file,
-                    &failed_extract_record_count
-                );
-                failed_reason_buf.write_all(failed_reason_string.as_bytes())
-                    .expect("failed to write string to file");
-                failed_reason_buf.write_all(b"\n")
-                    .expect("failed to write newline");
-                failed_reason_buf.flush().expect("failed to flush buffer");
-
-                write_when_fail(&fail_result_root, &record);
-                failed_extract_record_count += 1;
-                println!("failed_extract_record_count: {}", failed_extract_record_count);
+                let failure = Failure::new(FailureKind::Io, format!("Failed to read file {:?}: {}", &file_path, e)).at(&location);
+                failures.record(Some(&record), Some(&item_id), &failure)?;
+                journal.mark_item_done(&item_id)?;
                 continue;
-            }
+            },
         };
+        unparsed_log.record(&crate_name, crate_version.as_deref(), &rel_file, &parsed.skipped)?;
+        let ast = &parsed.file;
 
         // 尝试根据 CSV 提供的起始行号查找目标函数
-        let mut extracted_start_line:usize=0;
-        let mut extracted_end_line:usize=0;
         //println!("strat to find ItemFn");
-        let (fn_name, doc_comments) = if let Some(func) = find_function_by_start_line(&ast, start_line) {
+        let func = if let Some(func) = functions.find(ast, start_line) {
             //println!("Success find ItemFn");
-            let name = 
-            match &func{
-                FunctionMacroType::ItemFn(item_fn) => {
-                    extracted_start_line=item_fn.span().start().line;
-                    extracted_end_line=item_fn.span().end().line;
-                    item_fn.sig.ident.to_string()
-                },
-                FunctionMacroType::ForeignItemFn(foreign_item_fn) => {
-                    extracted_start_line=foreign_item_fn.span().start().line;
-                    extracted_end_line=foreign_item_fn.span().end().line;
-                    foreign_item_fn.sig.ident.to_string()
-                },
-                FunctionMacroType::ImplItemMethod(impl_item_method) => {
-                    extracted_start_line=impl_item_method.span().start().line;
-                    extracted_end_line=impl_item_method.span().end().line;
-                    impl_item_method.sig.ident.to_string()
-                },
-                FunctionMacroType::ItemMacro(item_macro) => {
-                    extracted_start_line=item_macro.span().start().line;
-                    extracted_end_line=item_macro.span().end().line;
-                    item_macro.ident.clone().map(|ident| ident.to_string()).unwrap_or_default()
-                },
-                FunctionMacroType::ItemMacro2(item_macro2) =>{
-                    extracted_start_line=item_macro2.span().start().line;
-                    extracted_end_line=item_macro2.span().end().line;
-                    item_macro2.ident.to_string()
-                },
-                FunctionMacroType::TraitItemMethod(trait_item_method) => {
-                    extracted_start_line=trait_item_method.span().start().line;
-                    extracted_end_line=trait_item_method.span().end().line;
-                    trait_item_method.sig.ident.to_string()
-                },
-            };
-            (name, extract_doc_comments(&func))
+            func
         } else {
             // 如果未能通过 AST 定位，则通过文本扫描尝试从指定行解析函数名
             /*let lines: Vec<&str> = source.lines().collect();
@@ -1068,43 +821,30 @@ fn main() {
                 "unknown".to_string()
             };*/
             //panic!("Failed to find_function_by_start_line {} {} {}",def_path,rel_file,start_line);
-            let failed_reason_file = OpenOptions::new()
-            .create(true)    // 不存在就创建
-            .append(true)    // 以追加模式，不会截断
-            .open(&fail_reason_path).expect("failed to open or create records_failed_to_extract.csv");
-            let mut failed_reason_buf = BufWriter::new(failed_reason_file);
-            let failed_reason_string=format!(
-                "Failed to find function by strat line {} {:?} {}\n failed_extract_record_count {}",
-                &crate_name,
-                &file_path,
-                &start_line,
-                &failed_extract_record_count
-            );
-            failed_reason_buf.write_all(failed_reason_string.as_bytes())
-                .expect("failed to write string to file");
-            failed_reason_buf.write_all(b"\n")
-                .expect("failed to write newline");
-            failed_reason_buf.flush().expect("failed to flush buffer");
-
-            write_when_fail(&fail_result_root, &record);
-            failed_extract_record_count+=1;
-            println!("failed_extract_record_count: {}",&failed_extract_record_count);
+            // 起始行落在无法解析的区域中时报告解析错误；def_path 指向别处时报告它指向的位置
+            let failure = match parsed.skipped_at(start_line) {
+                Some(region) => region.failure(&location),
+                None => locator::function_not_found(&def_path_sites, start_line, &file_path, &location),
+            };
+            failures.record(Some(&record), Some(&item_id), &failure)?;
+            journal.mark_item_done(&item_id)?;
             continue;
             //("Failed to find_function_by_start_line".to_string(), Vec::new())
         };
 
-        let has_doc = !doc_comments.is_empty();
-        let doc_paragraph = doc_comments.join(" ");
-        println!("Success find doc comments {}",doc_paragraph);
-        //println!("Success find doc comments");
-
-        // 使用文本扫描提取普通注释（基于函数名定位）
-        //println!("Start extract_inline_comments {} {}",extracted_start_line,extracted_end_line);
-        let inline_comments = extract_inline_comments(&source, extracted_start_line,extracted_end_line);
-        println!("Success extract_inline_comments");
-        let has_inline_comment = !inline_comments.is_empty();
-        let inline_comment_paragraph = inline_comments.join(" ");
-        println!("Success find normal comments");
+        let module_path = locator.module_path(Path::new(&rel_file), functions, ast, start_line);
+        if let Some(module_path) = &module_path {
+            if module_tree::def_path_in_module(&def_path, &crate_name, module_path) == Some(false) {
+                println!("def_path {} is not under module {}", def_path, module_tree::full_module_path(&crate_name, module_path));
+                def_path_mismatches += 1;
+            }
+        }
+        let mut status = function_comment_status(&crate_name, crate_version.as_deref(), def_path, rel_file, source, &func);
+        status.crate_checksum = opened_crate.as_ref().and_then(|opened| opened.checksum.clone());
+        status.module_path = module_path.map(|module_path| module_tree::full_module_path(&crate_name, &module_path));
+        println!("Success find doc comments {}",status.doc_paragraph);
+        let has_doc = status.has_doc;
+        let has_inline_comment = status.has_inline_comment;
 
         if has_doc{
             hasdocrecord+=1;
@@ -1116,58 +856,116 @@ fn main() {
             has_doc_or_comment+=1;
         }
         allhandlecount+=1;
-        results.push(FunctionCommentStatus {
-            crate_name:crate_name.clone(),
-            def_path,
-            file:rel_file,
-            line:extracted_start_line,
-            has_doc,
-            doc_paragraph,
-            has_inline_comment,
-            inline_comment_paragraph,
-        });
+        pending_item_ids.push(item_id);
+        results.push(status);
     }
 
-    let output_file_name="result-".to_owned()+&crate_name.clone()+".json";
-    let output_path = result_root.join(output_file_name);
-    let json = serde_json::to_string_pretty(&results)
-        .expect("Failed to serialize to JSON");
-
-    let mut result_file = OpenOptions::new()
-        .create(true)   // 文件不存在时创建
-        .append(true)   // 每次写入都追加到末尾，而不截断
-        .open(&output_path).expect("failed to open or create result.json");
+    if crate_name.is_empty() {
+        println!("no record left to extract");
+        return Ok(());
+    }
+    // 没有提取到任何函数时不写空的结果文件
+    if !results.is_empty() {
+        let output_path = write_crate_results(result_root, &crate_name, &results)?;
+        for done_item_id in pending_item_ids.drain(..) {
+            journal.mark_item_done(&done_item_id)?;
+        }
 
-    // 将 JSON 文本及换行写入文件末尾
-    if let Err(e) = result_file.write_all(json.as_bytes()) {
-        eprintln!("Failed to append to {:?}: {}", output_path, e);
-        return;
+        //println!("Results appended to {:?}", output_path);
+        //fs::write(&output_path, json)
+        //    .expect(&format!("Failed to write JSON to file: {:?}", output_path));
+        println!("Results written to {:?}", output_path);
     }
-    if let Err(e) = result_file.write_all(b"\n") {
-        eprintln!("Failed to append newline to {:?}: {}", output_path, e);
-        return;
+    journal.mark_crate_done(&crate_name, extract_index)?;
+
+    println!("extracted function count {}, failed record count {}", all_extracted_function_num, failures.count());
+    let (file_lookups, parsed_files) = ast_cache.stats();
+    println!("parsed {} files in {} lookups", parsed_files, file_lookups);
+    println!("located by def_path: {}", located_by_def_path);
+    println!("def_path not under the module of the found function: {}", def_path_mismatches);
+    println!("all handled{} has doc{} has inline comment{} has doc or comment{}",allhandlecount,hasdocrecord,hascommentcount,has_doc_or_comment);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 两个本地 crate：a 在输入中分成两段（第 1、2 条与第 4 条），b 只有第 3 条
+    fn resume_fixture(name: &str) -> PathBuf {
+        let base = std::env::temp_dir().join(format!("extract_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        for (crate_name, source) in [("a", "pub fn a1() {}\npub fn a2() {}\npub fn a3() {}\n"), ("b", "pub fn b1() {}\n")] {
+            fs::create_dir_all(base.join(crate_name).join("src")).unwrap();
+            fs::write(base.join(crate_name).join("src/lib.rs"), source).unwrap();
+        }
+        fs::create_dir_all(base.join("cache")).unwrap();
+        fs::write(
+            base.join("functions.csv"),
+            "item_id,crate,def_path,file,start_line,safety,version\n\
+             1,a,a::a1,A/src/lib.rs,1,Safe,\n\
+             2,a,a::a2,A/src/lib.rs,2,Safe,\n\
+             3,b,b::b1,B/src/lib.rs,1,Safe,\n\
+             4,a,a::a3,A/src/lib.rs,3,Safe,\n",
+        )
+        .unwrap();
+        base
     }
 
-    //println!("Results appended to {:?}", output_path);
-    //fs::write(&output_path, json)
-    //    .expect(&format!("Failed to write JSON to file: {:?}", output_path));
-    println!("Results written to {:?}", output_path);
-    
-    let now_crate_root_path=Path::new(&crate_root);
-    if now_crate_root_path.exists() {
-        match fs::remove_dir_all(&now_crate_root_path){
-            Ok(_) => {
-                println!("has deleted {:?}", &now_crate_root_path);
-            }
-            Err(_) => {
-                println!("failed to delete {:?}", &now_crate_root_path);
-            },
+    fn extract_args(base: &Path, resume: bool) -> ExtractArgs {
+        let path = |name: &str| base.join(name).to_string_lossy().into_owned();
+        let mut argv = vec!["extract_comment".to_owned(), "extract".to_owned(), "--headers".to_owned()];
+        argv.extend(["--functions".to_owned(), path("functions.csv"), "--result-dir".to_owned(), path("out")]);
+        argv.extend(["--cache-root".to_owned(), path("cache")]);
+        argv.extend(["--source-dir".to_owned(), format!("A={}", path("a")), "--source-dir".to_owned(), format!("B={}", path("b"))]);
+        if resume {
+            argv.push("--resume".to_owned());
         }
-        
-    } else {
-        println!("the dir does not exist {:?}", &now_crate_root_path);
-    }     
+        match Cli::try_parse_from(argv).unwrap().command {
+            Command::Extract(args) => args,
+            _ => unreachable!("parsed an extract command"),
+        }
+    }
 
-    println!("extracted function count {}", all_extracted_function_num);
-    println!("all handled{} has doc{} has inline comment{} has doc or comment{}",allhandlecount,hasdocrecord,hascommentcount,has_doc_or_comment);
+    fn extracted_def_paths(result_file: &Path) -> Vec<String> {
+        let text = fs::read_to_string(result_file).unwrap();
+        serde_json::Deserializer::from_str(&text)
+            .into_iter::<Vec<serde_json::Value>>()
+            .flat_map(|results| results.unwrap())
+            .map(|status| status["def_path"].as_str().unwrap().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn resume_skips_finished_items_and_crate_segments() {
+        let base = resume_fixture("resume");
+        let out = base.join("out");
+        fs::create_dir_all(&out).unwrap();
+        // 上次运行写完了 a 的第一段（只按 last_record 跳过）与 b 的唯一一条记录，在 a 的第二段之前中断
+        fs::write(
+            out.join(journal::JOURNAL_FILE_NAME),
+            "{\"kind\":\"crate\",\"crate_name\":\"a\",\"last_record\":2}\n{\"kind\":\"item\",\"item_id\":\"3\"}\n",
+        )
+        .unwrap();
+
+        run_extract(&extract_args(&base, true)).unwrap();
+        assert_eq!(extracted_def_paths(&out.join("result-a.json")), ["a::a3"]);
+        // b 没有剩下的记录，不写空的结果文件
+        assert!(!out.join("result-b.json").exists());
+
+        // 再次续跑时所有记录都已完成
+        run_extract(&extract_args(&base, true)).unwrap();
+        assert_eq!(extracted_def_paths(&out.join("result-a.json")), ["a::a3"]);
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn rerun_without_resume_extracts_everything() {
+        let base = resume_fixture("fresh");
+        let out = base.join("out");
+        run_extract(&extract_args(&base, false)).unwrap();
+        assert_eq!(extracted_def_paths(&out.join("result-a.json")), ["a::a1", "a::a2", "a::a3"]);
+        assert_eq!(extracted_def_paths(&out.join("result-b.json")), ["b::b1"]);
+        fs::remove_dir_all(&base).unwrap();
+    }
 }
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use syn::ext::IdentExt;
+use syn::{Item, ItemMod};
+
+use crate::ast_cache::AstCache;
+use crate::def_path::def_path_segments;
+use crate::vfs::{normalize, CrateFiles};
+
+/// crate 根文件，按顺序查找
+const ROOT_FILES: [&str; 2] = ["src/lib.rs", "src/main.rs"];
+
+/// crate 的模块树：从 src/lib.rs 与 src/main.rs 出发，沿 `mod foo;` 与 `#[path]` 找到的文件及其模块路径
+#[derive(Default)]
+pub struct ModuleTree {
+    /// 相对路径 -> 模块路径（不含 crate 名）
+    modules: HashMap<PathBuf, Vec<String>>,
+}
+
+/// 待读取的模块文件
+struct ModuleFile {
+    rel_file: PathBuf,
+    module_path: Vec<String>,
+    /// lib.rs、main.rs、mod.rs 以及由 `#[path]` 指定的文件：子模块文件在它所在的目录中查找
+    mod_rs: bool,
+}
+
+impl ModuleTree {
+    /// 解析经过 ast_cache，之后按文件查找函数时不会重复解析
+    pub fn build(crate_files: &CrateFiles, ast_cache: &mut AstCache) -> ModuleTree {
+        let mut tree = ModuleTree::default();
+        let mut pending: Vec<ModuleFile> = ROOT_FILES
+            .iter()
+            .rev()
+            .map(|root_file| ModuleFile {
+                rel_file: PathBuf::from(root_file),
+                module_path: Vec::new(),
+                mod_rs: true,
+            })
+            .collect();
+        while let Some(module_file) = pending.pop() {
+            // 同一个文件可能被多个 `mod` 引用（例如不同 cfg 下），只取第一次
+            if tree.modules.contains_key(&module_file.rel_file) || !crate_files.exists(&module_file.rel_file) {
+                continue;
+            }
+            let Ok(parsed_source) = ast_cache.get_or_parse(crate_files, &module_file.rel_file) else {
+                continue;
+            };
+            let file_dir = module_file.rel_file.parent().unwrap_or(Path::new("")).to_path_buf();
+            let mod_dir = if module_file.mod_rs {
+                file_dir.clone()
+            } else {
+                let stem = module_file.rel_file.file_stem().unwrap_or_default();
+                file_dir.join(stem)
+            };
+            let mut found = Vec::new();
+            find_module_files(crate_files, &parsed_source.parsed.file.items, &file_dir, &mod_dir, false, &module_file.module_path, &mut found);
+            // 逆序压栈，使文件按源码顺序处理
+            pending.extend(found.into_iter().rev());
+            tree.modules.insert(module_file.rel_file, module_file.module_path);
+        }
+        tree
+    }
+
+    /// 文件的模块路径（不含 crate 名）；不在模块树中的文件返回 None
+    pub fn module_path(&self, rel_file: &Path) -> Option<&[String]> {
+        let rel_file = normalize(rel_file)?;
+        self.modules.get(&rel_file).map(Vec::as_slice)
+    }
+
+    /// 模块树中的所有文件及其模块路径
+    pub fn files(&self) -> impl Iterator<Item = (&Path, &[String])> {
+        self.modules.iter().map(|(rel_file, module_path)| (rel_file.as_path(), module_path.as_slice()))
+    }
+}
+
+/// 在 items 中查找外部模块声明对应的文件
+///
+/// file_dir 是当前文件所在的目录，mod_dir 是当前（可能是内联的）模块的子模块所在目录。
+fn find_module_files(
+    crate_files: &CrateFiles,
+    items: &[Item],
+    file_dir: &Path,
+    mod_dir: &Path,
+    in_inline_mod: bool,
+    module_path: &[String],
+    found: &mut Vec<ModuleFile>,
+) {
+    for item in items {
+        let Item::Mod(item_mod) = item else {
+            continue;
+        };
+        let name = item_mod.ident.unraw().to_string();
+        let mut child_path = module_path.to_vec();
+        child_path.push(name.clone());
+        let path_attr = path_attribute(item_mod);
+        match &item_mod.content {
+            Some((_, mod_items)) => {
+                let child_dir = mod_dir.join(path_attr.as_deref().unwrap_or(&name));
+                find_module_files(crate_files, mod_items, file_dir, &child_dir, true, &child_path, found);
+            }
+            None => {
+                let candidates = match path_attr {
+                    // 内联模块之外的 `#[path]` 相对于当前文件所在目录，之内的相对于内联模块对应的目录
+                    Some(path) => {
+                        let base = if in_inline_mod { mod_dir } else { file_dir };
+                        vec![(base.join(path), true)]
+                    }
+                    None => vec![
+                        (mod_dir.join(format!("{}.rs", name)), false),
+                        (mod_dir.join(&name).join("mod.rs"), true),
+                    ],
+                };
+                let module_file = candidates
+                    .into_iter()
+                    .filter_map(|(rel_file, mod_rs)| Some((normalize(&rel_file)?, mod_rs)))
+                    .find(|(rel_file, _)| crate_files.exists(rel_file));
+                if let Some((rel_file, mod_rs)) = module_file {
+                    found.push(ModuleFile {
+                        rel_file,
+                        module_path: child_path,
+                        mod_rs,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// `#[path = "..."]` 的值
+fn path_attribute(item_mod: &ItemMod) -> Option<String> {
+    item_mod.attrs.iter().filter(|attr| attr.path.is_ident("path")).find_map(|attr| match attr.parse_meta() {
+        Ok(syn::Meta::NameValue(syn::MetaNameValue { lit: syn::Lit::Str(lit), .. })) => Some(lit.value()),
+        _ => None,
+    })
+}
+
+/// 带 crate 名的模块路径，例如 `my_crate::a::b`
+pub fn full_module_path(crate_name: &str, module_path: &[String]) -> String {
+    let mut segments = vec![crate_name.replace('-', "_")];
+    segments.extend(module_path.iter().cloned());
+    segments.join("::")
+}
+
+/// def_path 是否位于 module_path 表示的模块（或其子项）中
+///
+/// def_path 的第一段须是 crate 名或 `crate`，否则无法判断，返回 None。
+pub fn def_path_in_module(def_path: &str, crate_name: &str, module_path: &[String]) -> Option<bool> {
+    let segments = def_path_segments(def_path);
+    let (first, rest) = segments.split_first()?;
+    if *first != "crate" && *first != crate_name.replace('-', "_") {
+        return None;
+    }
+    if rest.len() <= module_path.len() {
+        return Some(false);
+    }
+    Some(
+        rest.iter()
+            .zip(module_path)
+            .all(|(segment, module)| segment.strip_prefix("r#").unwrap_or(segment) == module),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// 在临时目录中写出 crate 源码，返回 crate 根目录
+    fn write_crate(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("module_tree_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (rel_file, source) in files {
+            let path = root.join(rel_file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        root
+    }
+
+    fn module_path_of(tree: &ModuleTree, rel_file: &str) -> Option<String> {
+        tree.module_path(Path::new(rel_file)).map(|module_path| module_path.join("::"))
+    }
+
+    #[test]
+    fn resolves_mod_rs_path_attributes_and_inline_modules() {
+        let root = write_crate(
+            "resolve",
+            &[
+                (
+                    "src/lib.rs",
+                    "mod a;\nmod b;\nmod both;\n#[path = \"other/c_impl.rs\"]\nmod c;\n\
+                     mod inline {\n    mod d;\n    #[path = \"e_file.rs\"]\n    mod e;\n}\n\
+                     #[path = \"renamed\"]\nmod f {\n    mod g;\n}\n#[path = \"../helpers.rs\"]\nmod helpers;\n#[path = \"../../outside.rs\"]\nmod outside;\nmod r#type;\n",
+                ),
+                ("src/a.rs", "mod a1;\n"),
+                ("src/a/a1.rs", ""),
+                ("src/b/mod.rs", "mod b1;\n"),
+                ("src/b/b1.rs", ""),
+                ("src/both.rs", ""),
+                ("src/both/mod.rs", ""),
+                ("src/other/c_impl.rs", "mod c1;\n"),
+                ("src/other/c1.rs", ""),
+                ("src/inline/d.rs", ""),
+                ("src/inline/e_file.rs", ""),
+                ("src/renamed/g.rs", ""),
+                ("src/type.rs", ""),
+                ("src/orphan.rs", ""),
+                ("helpers.rs", ""),
+            ],
+        );
+        let tree = ModuleTree::build(&CrateFiles::Dir(root.clone()), &mut AstCache::default());
+
+        assert_eq!(module_path_of(&tree, "src/lib.rs").as_deref(), Some(""));
+        assert_eq!(module_path_of(&tree, "src/a.rs").as_deref(), Some("a"));
+        // 非 mod.rs 文件的子模块在与文件同名的目录中
+        assert_eq!(module_path_of(&tree, "src/a/a1.rs").as_deref(), Some("a::a1"));
+        assert_eq!(module_path_of(&tree, "src/b/mod.rs").as_deref(), Some("b"));
+        assert_eq!(module_path_of(&tree, "src/b/b1.rs").as_deref(), Some("b::b1"));
+        // foo.rs 与 foo/mod.rs 同时存在时取 foo.rs
+        assert_eq!(module_path_of(&tree, "src/both.rs").as_deref(), Some("both"));
+        assert_eq!(module_path_of(&tree, "src/both/mod.rs"), None);
+        // `#[path]` 指定的文件按 mod.rs 处理，子模块在它所在的目录中
+        assert_eq!(module_path_of(&tree, "src/other/c_impl.rs").as_deref(), Some("c"));
+        assert_eq!(module_path_of(&tree, "src/other/c1.rs").as_deref(), Some("c::c1"));
+        assert_eq!(module_path_of(&tree, "src/inline/d.rs").as_deref(), Some("inline::d"));
+        assert_eq!(module_path_of(&tree, "src/inline/e_file.rs").as_deref(), Some("inline::e"));
+        assert_eq!(module_path_of(&tree, "src/renamed/g.rs").as_deref(), Some("f::g"));
+        assert_eq!(module_path_of(&tree, "./src/type.rs").as_deref(), Some("type"));
+        assert_eq!(module_path_of(&tree, "src/orphan.rs"), None);
+        // `..` 可以回到 crate 根，但不能超出
+        assert_eq!(module_path_of(&tree, "helpers.rs").as_deref(), Some("helpers"));
+        assert!(tree.files().all(|(_, module_path)| module_path != ["outside"]));
+        assert_eq!(tree.files().count(), 13);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn def_path_in_module_compares_leading_segments() {
+        let module_path = ["a".to_owned(), "type".to_owned()];
+        assert_eq!(def_path_in_module("my_crate::a::r#type::f", "my-crate", &module_path), Some(true));
+        assert_eq!(def_path_in_module("crate::a::type::S::f", "my-crate", &module_path), Some(true));
+        assert_eq!(def_path_in_module("my_crate::a::f", "my-crate", &module_path), Some(false));
+        assert_eq!(def_path_in_module("other::a::type::f", "my-crate", &module_path), None);
+    }
+}
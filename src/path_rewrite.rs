@@ -0,0 +1,196 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+
+/// 任意 cargo-home 下 `registry/src/<index>/<name>-<version>/` 的前缀，覆盖 rustwide、其他 index hash 与 sparse registry
+const DEFAULT_REGISTRY_PATTERN: &str = r"^(?:.*/)?registry/src/[^/]+/[^/]+/";
+
+/// 把记录中的绝对路径改写为 crate 内相对路径的规则表
+///
+/// 规则按顺序尝试，第一条匹配的规则生效；规则文件中的规则排在内置的 registry 规则之前。
+#[derive(Debug)]
+pub struct PathRewriter {
+    rules: Vec<RewriteRule>,
+}
+
+#[derive(Debug)]
+struct RewriteRule {
+    /// 只匹配路径开头
+    pattern: Regex,
+    /// 替换匹配到的前缀，可以引用正则中的分组，例如 `$1/`
+    replace: String,
+}
+
+/// 规则文件中的一条规则，`regex` 与 `glob` 二选一
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    regex: Option<String>,
+    glob: Option<String>,
+    #[serde(default)]
+    replace: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleConfig>,
+}
+
+impl PathRewriter {
+    /// 读取规则文件（.toml 或 .json），再追加内置的 registry 规则
+    pub fn load(rules_path: Option<&Path>) -> Result<PathRewriter> {
+        let mut rules = Vec::new();
+        if let Some(rules_path) = rules_path {
+            let text = fs::read_to_string(rules_path)
+                .with_context(|| format!("cannot read path rewrite rules {:?}", rules_path))?;
+            let rules_file: RulesFile = if rules_path.extension().is_some_and(|ext| ext == "json") {
+                serde_json::from_str(&text).with_context(|| format!("invalid path rewrite rules {:?}", rules_path))?
+            } else {
+                toml::from_str(&text).with_context(|| format!("invalid path rewrite rules {:?}", rules_path))?
+            };
+            for (rule_index, rule) in rules_file.rules.into_iter().enumerate() {
+                let rule = RewriteRule::from_config(rule)
+                    .with_context(|| format!("invalid rule #{} in {:?}", rule_index + 1, rules_path))?;
+                rules.push(rule);
+            }
+        }
+        rules.push(RewriteRule {
+            pattern: Regex::new(DEFAULT_REGISTRY_PATTERN).expect("default registry pattern is valid"),
+            replace: String::new(),
+        });
+        Ok(PathRewriter { rules })
+    }
+
+    /// 用第一条匹配的规则改写路径；没有规则匹配时原样返回
+    pub fn rewrite(&self, file: &str) -> String {
+        // Windows 主机上生成的记录统一成 `/` 再匹配
+        let normalized = file.replace('\\', "/");
+        for rule in &self.rules {
+            if let Some(captures) = rule.pattern.captures(&normalized) {
+                let matched = captures.get(0).expect("group 0 always exists");
+                let mut rewritten = String::new();
+                captures.expand(&rule.replace, &mut rewritten);
+                rewritten.push_str(&normalized[matched.end()..]);
+                return rewritten;
+            }
+        }
+        file.to_owned()
+    }
+}
+
+/// 改写后的路径不是 crate 内的相对路径时返回原因：为空、是绝对路径或含有 `..`
+pub fn rel_file_error(rel_file: &str) -> Option<String> {
+    if rel_file.is_empty() {
+        return Some("rewritten file path is empty".to_owned());
+    }
+    // 没有规则匹配时路径原样返回，可能是 Windows 路径
+    if rel_file.starts_with(['/', '\\']) || rel_file.as_bytes().get(1) == Some(&b':') {
+        return Some(format!("rewritten file path {:?} is absolute", rel_file));
+    }
+    if rel_file.split(['/', '\\']).any(|segment| segment == "..") {
+        return Some(format!("rewritten file path {:?} leaves the crate root", rel_file));
+    }
+    None
+}
+
+impl RewriteRule {
+    fn from_config(config: RuleConfig) -> Result<RewriteRule> {
+        let pattern = match (config.regex, config.glob) {
+            (Some(regex), None) => {
+                // 规则只作用于路径前缀
+                let anchored = if regex.starts_with('^') { regex } else { format!("^(?:{})", regex) };
+                Regex::new(&anchored).with_context(|| format!("invalid regex {:?}", anchored))?
+            }
+            (None, Some(glob)) => glob_to_regex(&glob)?,
+            (Some(_), Some(_)) => bail!("rule has both regex and glob"),
+            (None, None) => bail!("rule needs either regex or glob"),
+        };
+        Ok(RewriteRule {
+            pattern,
+            replace: config.replace,
+        })
+    }
+}
+
+/// 把 glob 前缀转成正则：`*` 与 `?` 不跨越 `/`，`**` 匹配任意多层目录；匹配到的前缀包含其后的 `/`
+fn glob_to_regex(glob: &str) -> Result<Regex> {
+    let mut pattern = String::from("^");
+    let mut chars = glob.trim_end_matches('/').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                pattern.push_str(".*");
+            }
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('/');
+    Regex::new(&pattern).with_context(|| format!("invalid glob {:?}", glob))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_to_regex_stays_within_segments() {
+        let single = glob_to_regex("/home/*/src/").unwrap();
+        assert_eq!(single.as_str(), "^/home/[^/]*/src/");
+        assert!(single.is_match("/home/ci/src/lib.rs"));
+        assert!(!single.is_match("/home/ci/work/src/lib.rs"));
+        let deep = glob_to_regex("/build/**/crates").unwrap();
+        assert!(deep.is_match("/build/a/b/crates/foo/src/lib.rs"));
+        assert!(!deep.is_match("/build/crates-old/foo.rs"));
+        let question = glob_to_regex("/v?/x.y").unwrap();
+        assert!(question.is_match("/v1/x.y/z.rs"));
+        assert!(!question.is_match("/v1/xzy/z.rs"));
+        assert!(!question.is_match("/v12/x.y/z.rs"));
+    }
+
+    #[test]
+    fn default_rule_strips_any_registry_prefix() {
+        let rewriter = PathRewriter::load(None).unwrap();
+        let rewrite = |file| rewriter.rewrite(file);
+        assert_eq!(
+            rewrite("/home/u/.cargo/registry/src/index.crates.io-6f17d22bba15001f/md-5-0.10.6/src/lib.rs"),
+            "src/lib.rs"
+        );
+        assert_eq!(rewrite("/opt/rustwide/cargo-home/registry/src/github.com-1ecc6299db9ec823/libc-0.2.1/src/unix/mod.rs"), "src/unix/mod.rs");
+        assert_eq!(rewrite(r"C:\Users\u\.cargo\registry\src\index.crates.io-abc\itoa-1.0.0\src\lib.rs"), "src/lib.rs");
+        assert_eq!(rewrite("registry/src/idx/serde-1.0.0/build.rs"), "build.rs");
+        // 不匹配时原样返回
+        assert_eq!(rewrite("/tmp/checkout/src/lib.rs"), "/tmp/checkout/src/lib.rs");
+    }
+
+    #[test]
+    fn rules_file_rules_come_before_the_default_rule() {
+        let rules_path = std::env::temp_dir().join(format!("path_rewrite_rules_{}.toml", std::process::id()));
+        fs::write(
+            &rules_path,
+            "[[rule]]\nglob = \"/srv/**/registry/src/*/\"\nreplace = \"vendored/\"\n\n[[rule]]\nregex = \"/work/([^/]+)/\"\nreplace = \"$1/\"\n",
+        )
+        .unwrap();
+        let rewriter = PathRewriter::load(Some(&rules_path)).unwrap();
+        fs::remove_file(&rules_path).unwrap();
+        assert_eq!(rewriter.rewrite("/srv/a/registry/src/idx/foo-1.0.0/src/lib.rs"), "vendored/foo-1.0.0/src/lib.rs");
+        assert_eq!(rewriter.rewrite("/work/foo/src/lib.rs"), "foo/src/lib.rs");
+        assert_eq!(rewriter.rewrite("/home/u/.cargo/registry/src/idx/foo-1.0.0/src/lib.rs"), "src/lib.rs");
+    }
+
+    #[test]
+    fn rel_file_error_rejects_paths_outside_the_crate() {
+        assert_eq!(rel_file_error("src/lib.rs"), None);
+        assert_eq!(rel_file_error("src/..rs/x.rs"), None);
+        for rel_file in ["", "/tmp/rv/bad/src/lib.rs", r"\server\share\lib.rs", r"C:\src\lib.rs", "../lib.rs", "src/../../lib.rs", r"src\..\..\lib.rs"] {
+            assert!(rel_file_error(rel_file).is_some(), "{:?} should be rejected", rel_file);
+        }
+    }
+}
@@ -0,0 +1,432 @@
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use proc_macro2::{Delimiter, Spacing, Span, TokenStream, TokenTree};
+use serde::Serialize;
+use syn::{File, Item};
+
+use crate::failure::{Failure, FailureKind, FailureLocation};
+
+const UNPARSED_FILE_NAME: &str = "unparsed_regions.jsonl";
+
+/// 容错解析的结果：能解析的顶层条目，以及跳过的区域
+pub struct RecoveredFile {
+    pub file: File,
+    /// 无法解析的区域，按行号顺序；整个文件一次解析成功时为空
+    pub skipped: Vec<SkippedRegion>,
+}
+
+/// 一段无法解析的源码及其原因
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedRegion {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub kind: FailureKind,
+    pub message: String,
+    /// 错误所在的行列，取不到时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+}
+
+impl SkippedRegion {
+    /// 记录指向的函数落在这个区域中时的失败
+    pub fn failure(&self, location: &FailureLocation) -> Failure {
+        let mut failure = Failure::new(self.kind, self.message.clone()).at(location);
+        if self.line.is_some() {
+            failure.location.line = self.line;
+            failure.location.column = self.column;
+        }
+        failure
+    }
+}
+
+impl RecoveredFile {
+    /// 包含 line 的跳过区域
+    pub fn skipped_at(&self, line: usize) -> Option<&SkippedRegion> {
+        self.skipped
+            .iter()
+            .find(|region| region.start_line <= line && line <= region.end_line)
+    }
+
+    /// 按顶层条目切分 tokens 并逐个解析
+    ///
+    /// 切分点是顶层的 `;`，以及不在 `=` 之后的 `{}`；条目的 span 仍指向原文件。
+    fn push_items(&mut self, tokens: TokenStream) {
+        let mut chunk: Vec<TokenTree> = Vec::new();
+        // 当前条目中是否已出现顶层的 `=`（例如 const、static 的初始值），此时 `{}` 不是条目结尾
+        let mut after_eq = false;
+        // `<...>` 的嵌套深度，其中的 `=` 属于泛型参数（`Iterator<Item = u8>`）
+        let mut angle_depth = 0usize;
+        let mut previous_joint_dash = false;
+        for token in tokens {
+            let end_of_item = match &token {
+                TokenTree::Punct(punct) => {
+                    let is_end = match punct.as_char() {
+                        ';' => true,
+                        '<' => {
+                            angle_depth += 1;
+                            false
+                        }
+                        // `->` 中的 `>` 不关闭尖括号
+                        '>' if !previous_joint_dash => {
+                            angle_depth = angle_depth.saturating_sub(1);
+                            false
+                        }
+                        '=' if angle_depth == 0 => {
+                            after_eq = true;
+                            false
+                        }
+                        _ => false,
+                    };
+                    previous_joint_dash = punct.as_char() == '-' && punct.spacing() == Spacing::Joint;
+                    is_end
+                }
+                TokenTree::Group(group) => {
+                    previous_joint_dash = false;
+                    group.delimiter() == Delimiter::Brace && !after_eq
+                }
+                _ => {
+                    previous_joint_dash = false;
+                    false
+                }
+            };
+            chunk.push(token);
+            // 内部属性 `#![...]`（包括 `//!`）不属于任何条目
+            if is_inner_attribute(&chunk) {
+                chunk.clear();
+                continue;
+            }
+            if end_of_item {
+                self.push_chunk(&mut chunk);
+                after_eq = false;
+                angle_depth = 0;
+            }
+        }
+        self.push_chunk(&mut chunk);
+    }
+
+    fn push_chunk(&mut self, chunk: &mut Vec<TokenTree>) {
+        let (Some(first), Some(last)) = (chunk.first(), chunk.last()) else {
+            return;
+        };
+        let start_line = first.span().start().line;
+        let end_line = last.span().end().line;
+        let tokens: TokenStream = chunk.drain(..).collect();
+        let (kind, message, error_span) = match catch_unwind(AssertUnwindSafe(|| syn::parse2::<Item>(tokens))) {
+            Ok(Ok(item)) => {
+                self.file.items.push(item);
+                return;
+            }
+            Ok(Err(parse_err)) => (FailureKind::ParseError, parse_err.to_string(), Some(parse_err.span())),
+            Err(panic_payload) => {
+                let failure = Failure::parse_panic(&*panic_payload, &FailureLocation::default());
+                (failure.kind, failure.message, None)
+            }
+        };
+        self.skipped.push(region(start_line, end_line, kind, message, error_span));
+    }
+}
+
+/// 先整体解析；失败（包括 panic）时逐个解析顶层条目
+pub fn parse_tolerant(source: &str) -> RecoveredFile {
+    match catch_unwind(|| syn::parse_str::<File>(source)) {
+        Ok(Ok(file)) => RecoveredFile {
+            file,
+            skipped: Vec::new(),
+        },
+        _ => parse_items(source),
+    }
+}
+
+/// 把文件切成顶层条目逐个解析，返回能解析的条目与跳过的区域
+///
+/// 通常先对整个文件做词法分析再按 token 切分；整个文件无法词法分析时（例如未闭合的字符串），
+/// 先按括号平衡的行切开，每段单独词法分析，把无法分析的段换成同样多的空行后再对整个文件做词法分析，
+/// 这样 span 的行号与原文件一致。
+pub fn parse_items(source: &str) -> RecoveredFile {
+    let mut recovered = RecoveredFile {
+        file: File {
+            shebang: None,
+            attrs: Vec::new(),
+            items: Vec::new(),
+        },
+        skipped: Vec::new(),
+    };
+    match source.parse::<TokenStream>() {
+        Ok(tokens) => recovered.push_items(tokens),
+        Err(_) => {
+            let mut lexable = String::with_capacity(source.len());
+            for (start_line, text) in split_lines_at_items(source) {
+                if let Err(lex_err) = text.parse::<TokenStream>() {
+                    let line_count = text.lines().count().max(1);
+                    let message = format!("lex error: {}", lex_err);
+                    let mut skipped = region(start_line, start_line + line_count - 1, FailureKind::ParseError, message, Some(lex_err.span()));
+                    // 错误位置是段内的行号
+                    skipped.line = skipped.line.map(|line| line + start_line - 1);
+                    recovered.skipped.push(skipped);
+                    lexable.extend(std::iter::repeat_n('\n', line_count));
+                } else {
+                    lexable.push_str(&text);
+                }
+            }
+            match lexable.parse::<TokenStream>() {
+                Ok(tokens) => recovered.push_items(tokens),
+                // 各段单独都能词法分析、拼起来却不能时，整个文件作为一个区域跳过
+                Err(lex_err) => {
+                    let message = format!("lex error: {}", lex_err);
+                    let end_line = source.lines().count().max(1);
+                    recovered.skipped = vec![region(1, end_line, FailureKind::ParseError, message, Some(lex_err.span()))];
+                }
+            }
+        }
+    }
+    recovered.skipped.sort_by_key(|region| region.start_line);
+    recovered
+}
+
+fn region(start_line: usize, end_line: usize, kind: FailureKind, message: String, error_span: Option<Span>) -> SkippedRegion {
+    // 取不到位置的 span 行号为 0
+    let error_start = error_span.map(|span| span.start()).filter(|start| start.line > 0);
+    SkippedRegion {
+        start_line,
+        end_line,
+        kind,
+        message,
+        line: error_start.map(|start| start.line),
+        column: error_start.map(|start| start.column + 1),
+    }
+}
+
+fn is_inner_attribute(chunk: &[TokenTree]) -> bool {
+    match chunk {
+        [TokenTree::Punct(hash), TokenTree::Punct(bang), TokenTree::Group(group)] => {
+            hash.as_char() == '#' && bang.as_char() == '!' && group.delimiter() == Delimiter::Bracket
+        }
+        _ => false,
+    }
+}
+
+/// 按行切分顶层条目：一行结束时括号已经平衡，且该行最后一个有效字符是 `}` 或 `;`，即为条目边界
+///
+/// 返回每段的起始行号（从 1 开始）与文本。字符串、字符字面量与注释中的括号不计入。
+fn split_lines_at_items(source: &str) -> Vec<(usize, String)> {
+    let mut scanner = LineScanner::default();
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    let mut chunk_start = 1;
+    for (line_index, line) in source.lines().enumerate() {
+        if chunk.is_empty() {
+            chunk_start = line_index + 1;
+        }
+        chunk.push_str(line);
+        chunk.push('\n');
+        let last = scanner.scan_line(line);
+        if scanner.at_top_level() && matches!(last, Some('}') | Some(';')) {
+            chunks.push((chunk_start, std::mem::take(&mut chunk)));
+        }
+    }
+    if !chunk.trim().is_empty() {
+        chunks.push((chunk_start, chunk));
+    }
+    chunks
+}
+
+/// 跨行的词法状态，只关心括号深度
+#[derive(Debug, Default)]
+struct LineScanner {
+    depth: isize,
+    block_comment_depth: usize,
+    /// 正在读取的字符串：None 为不在字符串中，Some(None) 为普通字符串，Some(Some(n)) 为带 n 个 `#` 的原始字符串
+    string: Option<Option<usize>>,
+}
+
+impl LineScanner {
+    fn at_top_level(&self) -> bool {
+        self.depth <= 0 && self.block_comment_depth == 0 && self.string.is_none()
+    }
+
+    /// 扫描一行，返回注释与字符串之外的最后一个非空白字符
+    fn scan_line(&mut self, line: &str) -> Option<char> {
+        let chars: Vec<char> = line.chars().collect();
+        let at = |index: usize| chars.get(index).copied();
+        let is_ident_char = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let mut last = None;
+        let mut index = 0;
+        while index < chars.len() {
+            let c = chars[index];
+            if self.block_comment_depth > 0 {
+                match (c, at(index + 1)) {
+                    ('*', Some('/')) => {
+                        self.block_comment_depth -= 1;
+                        index += 2;
+                    }
+                    ('/', Some('*')) => {
+                        self.block_comment_depth += 1;
+                        index += 2;
+                    }
+                    _ => index += 1,
+                }
+                continue;
+            }
+            match self.string {
+                Some(None) => {
+                    match c {
+                        '\\' => index += 1,
+                        '"' => {
+                            self.string = None;
+                            last = Some('"');
+                        }
+                        _ => {}
+                    }
+                    index += 1;
+                    continue;
+                }
+                Some(Some(hashes)) => {
+                    if c == '"' && (1..=hashes).all(|offset| at(index + offset) == Some('#')) {
+                        self.string = None;
+                        last = Some('"');
+                        index += hashes;
+                    }
+                    index += 1;
+                    continue;
+                }
+                None => {}
+            }
+            match c {
+                '/' if at(index + 1) == Some('/') => break,
+                '/' if at(index + 1) == Some('*') => {
+                    self.block_comment_depth += 1;
+                    index += 2;
+                    continue;
+                }
+                '"' => self.string = Some(None),
+                // r"..."、r#"..."#、br"..."
+                'r' if !is_ident_char(index.checked_sub(1).and_then(at))
+                    || (index >= 1 && at(index - 1) == Some('b') && !is_ident_char(index.checked_sub(2).and_then(at))) =>
+                {
+                    let hashes = chars[index + 1..].iter().take_while(|c| **c == '#').count();
+                    if at(index + 1 + hashes) == Some('"') {
+                        self.string = Some(Some(hashes));
+                        index += 2 + hashes;
+                        continue;
+                    }
+                }
+                // 字符字面量；`'a` 这样后面没有闭合引号的是生命周期
+                '\'' => {
+                    if at(index + 1) == Some('\\') {
+                        let close = chars[index + 2..].iter().position(|c| *c == '\'');
+                        index = close.map_or(chars.len(), |close| index + 3 + close);
+                        last = Some('\'');
+                        continue;
+                    }
+                    if at(index + 2) == Some('\'') {
+                        index += 3;
+                        last = Some('\'');
+                        continue;
+                    }
+                }
+                '{' | '(' | '[' => self.depth += 1,
+                '}' | ')' | ']' => self.depth -= 1,
+                _ => {}
+            }
+            if !c.is_whitespace() {
+                last = Some(c);
+            }
+            index += 1;
+        }
+        last
+    }
+}
+
+/// 把各文件中跳过的区域写入结果目录中的 unparsed_regions.jsonl，同一个文件只记录一次
+#[derive(Debug)]
+pub struct UnparsedLog {
+    path: PathBuf,
+    seen: HashSet<(String, Option<String>, String)>,
+}
+
+#[derive(Debug, Serialize)]
+struct UnparsedLine<'a> {
+    crate_name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crate_version: Option<&'a str>,
+    file: &'a str,
+    #[serde(flatten)]
+    region: &'a SkippedRegion,
+}
+
+impl UnparsedLog {
+    pub fn new(result_root: &Path) -> UnparsedLog {
+        UnparsedLog {
+            path: result_root.join(UNPARSED_FILE_NAME),
+            seen: HashSet::new(),
+        }
+    }
+
+    pub fn record(&mut self, crate_name: &str, crate_version: Option<&str>, file: &str, skipped: &[SkippedRegion]) -> Result<()> {
+        if skipped.is_empty()
+            || !self
+                .seen
+                .insert((crate_name.to_owned(), crate_version.map(str::to_owned), file.to_owned()))
+        {
+            return Ok(());
+        }
+        let mut log_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("cannot open or create {:?}", self.path))?;
+        for region in skipped {
+            let line = serde_json::to_string(&UnparsedLine {
+                crate_name,
+                crate_version,
+                file,
+                region,
+            })
+            .context("cannot serialize unparsed region")?;
+            writeln!(log_file, "{}", line).with_context(|| format!("cannot write {:?}", self.path))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::spanned::Spanned;
+
+    use super::*;
+
+    #[test]
+    fn lex_errors_keep_line_numbers() {
+        let source = "fn a() {}\n\nfn b() {\n    let s = '\\q';\n}\n\n/// c\nfn c() {\n}\n";
+        assert!(source.parse::<TokenStream>().is_err());
+        let recovered = parse_items(source);
+        let lines: Vec<(usize, usize)> = recovered
+            .file
+            .items
+            .iter()
+            .map(|item| (item.span().start().line, item.span().end().line))
+            .collect();
+        assert_eq!(lines, [(1, 1), (7, 9)]);
+        assert_eq!(recovered.skipped.len(), 1);
+        let skipped = &recovered.skipped[0];
+        // 空行并入下一段
+        assert_eq!((skipped.start_line, skipped.end_line), (2, 5));
+        assert_eq!(skipped.line, Some(4));
+        assert!(recovered.skipped_at(4).is_some());
+        assert!(recovered.skipped_at(8).is_none());
+    }
+
+    #[test]
+    fn split_lines_at_items_numbers_chunks_from_one() {
+        let source = "use a;\nfn f() {\n    \"}\";\n}\n/* } */ struct S;\nfn g() {}";
+        let starts: Vec<usize> = split_lines_at_items(source).iter().map(|(start_line, _)| *start_line).collect();
+        assert_eq!(starts, [1, 2, 5, 6]);
+    }
+}
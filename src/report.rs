@@ -0,0 +1,314 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+
+use crate::cli::ReportArgs;
+use crate::failure::{Failure, FailureKind, FailureLine, FAILURES_FILE_NAME};
+
+/// report 的输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+/// 汇总后的失败报告，JSON 输出即为此结构
+#[derive(Debug, Serialize)]
+struct FailureReport {
+    total: usize,
+    by_kind: Vec<FailureGroup>,
+    by_crate: Vec<FailureGroup>,
+    /// 按文件所在位置分组：src、tests、benches、examples、build.rs、OUT_DIR 中生成的文件等
+    by_file_kind: Vec<FailureGroup>,
+    by_extension: Vec<FailureGroup>,
+}
+
+#[derive(Debug, Serialize)]
+struct FailureGroup {
+    key: String,
+    count: usize,
+    percent: f64,
+    /// 组内各类失败的数量；按类别分组时为空
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    kinds: BTreeMap<FailureKind, usize>,
+    examples: Vec<FailureExample>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct FailureExample {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    item_id: Option<String>,
+    #[serde(flatten)]
+    failure: Failure,
+}
+
+/// report 子命令：按类别、crate 与文件位置汇总结果目录中的 failures.jsonl
+pub fn run_report(args: &ReportArgs) -> Result<()> {
+    let failures_path = args.result_dir.join(FAILURES_FILE_NAME);
+    if !failures_path.is_file() {
+        bail!(
+            "{:?} does not exist; it is written by extract, scan and retry runs of this version",
+            failures_path
+        );
+    }
+    let lines = read_failure_lines(&failures_path)?;
+    let report = build_report(&lines, args.examples, args.top);
+    let output = match args.format {
+        ReportFormat::Json => serde_json::to_string_pretty(&report).context("cannot serialize failure report")?,
+        ReportFormat::Text => render_text(&report, &args.result_dir),
+    };
+    match &args.output {
+        Some(output_path) => {
+            std::fs::write(output_path, output).with_context(|| format!("cannot write {:?}", output_path))?;
+            println!("Report written to {:?}", output_path);
+        }
+        None => println!("{}", output),
+    }
+    Ok(())
+}
+
+fn read_failure_lines(failures_path: &Path) -> Result<Vec<FailureLine>> {
+    let file = File::open(failures_path).with_context(|| format!("cannot open {:?}", failures_path))?;
+    let mut lines = Vec::new();
+    for (line_index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("cannot read {:?}", failures_path))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let failure_line: FailureLine = serde_json::from_str(&line)
+            .with_context(|| format!("invalid failure at line {} of {:?}", line_index + 1, failures_path))?;
+        lines.push(failure_line);
+    }
+    Ok(lines)
+}
+
+fn build_report(lines: &[FailureLine], example_count: usize, top: usize) -> FailureReport {
+    let total = lines.len();
+    let group_by = |key_of: &dyn Fn(&FailureLine) -> String, with_kinds: bool| {
+        let mut groups: HashMap<String, FailureGroup> = HashMap::new();
+        for line in lines {
+            let key = key_of(line);
+            let group = groups.entry(key.clone()).or_insert_with(|| FailureGroup {
+                key,
+                count: 0,
+                percent: 0.0,
+                kinds: BTreeMap::new(),
+                examples: Vec::new(),
+            });
+            group.count += 1;
+            if with_kinds {
+                *group.kinds.entry(line.failure.kind).or_default() += 1;
+            }
+            if group.examples.len() < example_count {
+                group.examples.push(FailureExample {
+                    item_id: line.item_id.clone(),
+                    failure: line.failure.clone(),
+                });
+            }
+        }
+        let mut groups: Vec<FailureGroup> = groups.into_values().collect();
+        for group in &mut groups {
+            group.percent = percent(group.count, total);
+        }
+        // 数量多的在前，数量相同时按键排序，使输出稳定
+        groups.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+        if top > 0 {
+            groups.truncate(top);
+        }
+        groups
+    };
+    FailureReport {
+        total,
+        by_kind: group_by(&|line| line.failure.kind.to_string(), false),
+        by_crate: group_by(
+            &|line| line.failure.location.crate_name.clone().unwrap_or_else(|| "(unknown)".to_owned()),
+            true,
+        ),
+        by_file_kind: group_by(
+            &|line| line.failure.location.file.as_deref().map(file_kind).unwrap_or("(unknown)").to_owned(),
+            true,
+        ),
+        by_extension: group_by(
+            &|line| match line.failure.location.file.as_deref() {
+                Some(file) => file_extension(file),
+                None => "(unknown)".to_owned(),
+            },
+            true,
+        ),
+    }
+}
+
+fn percent(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    // 保留一位小数
+    (count as f64 * 1000.0 / total as f64).round() / 10.0
+}
+
+/// 文件在 crate 中的位置类别
+fn file_kind(file: &str) -> &'static str {
+    let normalized = file.replace('\\', "/");
+    let parts: Vec<&str> = normalized.split('/').filter(|part| !part.is_empty()).collect();
+    // build script 生成的文件位于 target/<profile>/build/<pkg>-<hash>/out/ 下
+    if parts.windows(3).any(|window| window[0] == "build" && window[2] == "out") {
+        return "out_dir";
+    }
+    match parts.last() {
+        Some(&"build.rs") => return "build.rs",
+        None => return "(unknown)",
+        _ => {}
+    }
+    let directories = &parts[..parts.len() - 1];
+    for (directory, kind) in [("tests", "tests"), ("benches", "benches"), ("examples", "examples")] {
+        if directories.contains(&directory) {
+            return kind;
+        }
+    }
+    if directories.contains(&"src") {
+        "src"
+    } else {
+        "other"
+    }
+}
+
+fn file_extension(file: &str) -> String {
+    match Path::new(file).extension() {
+        Some(extension) => format!(".{}", extension.to_string_lossy()),
+        None => "(none)".to_owned(),
+    }
+}
+
+fn render_text(report: &FailureReport, result_dir: &Path) -> String {
+    let mut text = String::new();
+    let _ = writeln!(text, "failures in {:?}: {}", result_dir, report.total);
+    let sections = [
+        ("by kind", &report.by_kind),
+        ("by crate", &report.by_crate),
+        ("by file kind", &report.by_file_kind),
+        ("by extension", &report.by_extension),
+    ];
+    for (title, groups) in sections {
+        let _ = writeln!(text, "\n{}:", title);
+        let key_width = groups.iter().map(|group| group.key.len()).max().unwrap_or(0);
+        for group in groups {
+            let _ = write!(text, "  {:<width$}  {:>6}  {:>5.1}%", group.key, group.count, group.percent, width = key_width);
+            if !group.kinds.is_empty() {
+                let kinds: Vec<String> = group.kinds.iter().map(|(kind, count)| format!("{} {}", kind, count)).collect();
+                let _ = write!(text, "  ({})", kinds.join(", "));
+            }
+            text.push('\n');
+            for example in &group.examples {
+                match &example.item_id {
+                    Some(item_id) => {
+                        let _ = writeln!(text, "      e.g. {} {}", item_id, example.failure);
+                    }
+                    None => {
+                        let _ = writeln!(text, "      e.g. {}", example.failure);
+                    }
+                }
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::failure::FailureLocation;
+
+    fn failure_line(kind: FailureKind, crate_name: Option<&str>, file: Option<&str>) -> FailureLine {
+        let location = FailureLocation {
+            crate_name: crate_name.map(str::to_owned),
+            file: file.map(str::to_owned),
+            ..FailureLocation::default()
+        };
+        FailureLine {
+            item_id: None,
+            failure: Failure::new(kind, "test failure").at(&location),
+            record: serde_json::Value::Null,
+        }
+    }
+
+    fn group<'a>(groups: &'a [FailureGroup], key: &str) -> &'a FailureGroup {
+        groups.iter().find(|group| group.key == key).unwrap_or_else(|| panic!("no group {:?}", key))
+    }
+
+    fn keys(groups: &[FailureGroup]) -> Vec<&str> {
+        groups.iter().map(|group| group.key.as_str()).collect()
+    }
+
+    #[test]
+    fn file_kind_classifies_crate_locations() {
+        assert_eq!(file_kind("src/lib.rs"), "src");
+        assert_eq!(file_kind("src\\parser\\mod.rs"), "src");
+        assert_eq!(file_kind("tests/common/mod.rs"), "tests");
+        assert_eq!(file_kind("benches/bench.rs"), "benches");
+        assert_eq!(file_kind("examples/demo.rs"), "examples");
+        assert_eq!(file_kind("build.rs"), "build.rs");
+        assert_eq!(file_kind("target/debug/build/foo-0123abcd/out/bindings.rs"), "out_dir");
+        assert_eq!(file_kind("lib.rs"), "other");
+        assert_eq!(file_kind(""), "(unknown)");
+    }
+
+    #[test]
+    fn groups_by_kind_crate_and_file() {
+        let lines = vec![
+            failure_line(FailureKind::ParseError, Some("a"), Some("src/lib.rs")),
+            failure_line(FailureKind::ParseError, Some("a"), Some("tests/it.rs")),
+            failure_line(FailureKind::FunctionNotFound, Some("a"), Some("src/util.rs")),
+            failure_line(FailureKind::FileMissing, Some("b"), Some("build.rs")),
+            failure_line(FailureKind::ParseError, Some("b"), Some("target/release/build/b-1/out/gen.rs")),
+            failure_line(FailureKind::InvalidRecord, None, None),
+        ];
+        let report = build_report(&lines, 5, 0);
+        assert_eq!(report.total, 6);
+
+        assert_eq!(
+            keys(&report.by_kind),
+            ["parse_error", "file_missing", "function_not_found", "invalid_record"]
+        );
+        let parse_error = group(&report.by_kind, "parse_error");
+        assert_eq!(parse_error.count, 3);
+        assert_eq!(parse_error.percent, 50.0);
+        assert!(parse_error.kinds.is_empty());
+
+        assert_eq!(keys(&report.by_crate), ["a", "b", "(unknown)"]);
+        let crate_a = group(&report.by_crate, "a");
+        assert_eq!(crate_a.count, 3);
+        assert_eq!(crate_a.kinds.get(&FailureKind::ParseError), Some(&2));
+        assert_eq!(crate_a.kinds.get(&FailureKind::FunctionNotFound), Some(&1));
+        assert_eq!(group(&report.by_crate, "(unknown)").percent, 16.7);
+
+        assert_eq!(keys(&report.by_file_kind), ["src", "(unknown)", "build.rs", "out_dir", "tests"]);
+        let src = group(&report.by_file_kind, "src");
+        assert_eq!(src.count, 2);
+        assert_eq!(src.examples.len(), 2);
+        assert_eq!(group(&report.by_file_kind, "out_dir").kinds.get(&FailureKind::ParseError), Some(&1));
+
+        assert_eq!(group(&report.by_extension, ".rs").count, 5);
+        assert_eq!(group(&report.by_extension, "(unknown)").count, 1);
+    }
+
+    #[test]
+    fn top_and_example_limits_apply_to_every_group() {
+        let lines = vec![
+            failure_line(FailureKind::ParseError, Some("a"), Some("src/lib.rs")),
+            failure_line(FailureKind::ParseError, Some("a"), Some("src/lib.rs")),
+            failure_line(FailureKind::ParseError, Some("a"), Some("src/lib.rs")),
+            failure_line(FailureKind::FileMissing, Some("b"), Some("src/lib.rs")),
+            failure_line(FailureKind::Io, Some("c"), Some("src/lib.rs")),
+        ];
+        let report = build_report(&lines, 1, 2);
+        assert_eq!(keys(&report.by_kind), ["parse_error", "file_missing"]);
+        assert_eq!(keys(&report.by_crate), ["a", "b"]);
+        assert!(report.by_kind.iter().all(|group| group.examples.len() == 1));
+        assert_eq!(group(&report.by_file_kind, "src").count, 5);
+    }
+}
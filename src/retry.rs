@@ -0,0 +1,379 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use syn::File;
+
+use crate::ast_cache::{AstCache, ParsedSource};
+use crate::cli::RetryArgs;
+use crate::crate_source::{CrateCache, CrateLocation, LocatedCrate, OpenedCrate};
+use crate::failure::{Failure, FailureKind, FailureLocation, FailureSink, FAILED_RECORDS_FILE_NAME};
+use crate::input::{FunctionRecord, InputFormat, InputRow, RecordSource};
+use crate::locator::{self, FunctionLocator};
+use crate::module_tree::full_module_path;
+use crate::path_rewrite::{rel_file_error, PathRewriter};
+use crate::safe_unpack::record_rejections;
+use crate::function_index::FunctionIndex;
+use crate::{function_comment_status, write_crate_results, FunctionCommentStatus, FunctionMacroType};
+
+const SUMMARY_FILE_NAME: &str = "retry_summary.json";
+
+/// retry 中可以启用的补救策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryStrategy {
+    /// 按备选改写规则，或按路径后缀在 crate 中查找记录指向的文件
+    PathRewrite,
+    /// 起始行上没有函数时，在上下若干行内查找同名函数
+    FuzzyLine,
+    /// 整个文件无法解析时逐个解析顶层条目，跳过无法解析的区域
+    LenientParse,
+}
+
+const ALL_STRATEGIES: [RetryStrategy; 3] = [RetryStrategy::PathRewrite, RetryStrategy::FuzzyLine, RetryStrategy::LenientParse];
+
+/// 一次 retry 的统计，写入输出目录中的 retry_summary.json
+#[derive(Debug, Default, Serialize)]
+struct RetrySummary {
+    retried: usize,
+    recovered: usize,
+    still_failed: usize,
+    /// 不需要任何策略就成功的记录，例如缓存中补上了缺少的 crate
+    recovered_as_is: usize,
+    /// 每个策略参与恢复的记录数；一条记录用到多个策略时分别计数
+    recovered_by_strategy: BTreeMap<RetryStrategy, usize>,
+}
+
+/// 恢复成功的一条记录及其用到的策略
+struct Recovered {
+    status: FunctionCommentStatus,
+    strategies: Vec<RetryStrategy>,
+}
+
+/// 当前打开的 crate，连续的同一 crate 的记录共用
+struct CurrentCrate {
+    crate_name: String,
+    crate_version: Option<String>,
+    source_root: Option<PathBuf>,
+    opened: Result<OpenedCrate, Failure>,
+}
+
+struct Retrier {
+    crate_cache: CrateCache,
+    path_rewriter: PathRewriter,
+    alt_path_rewriters: Vec<PathRewriter>,
+    strategies: Vec<RetryStrategy>,
+    line_window: usize,
+    current: Option<CurrentCrate>,
+    /// 当前 crate 中已解析的文件
+    ast_cache: AstCache,
+    /// 与 extract 相同的 def_path 与模块树定位
+    locator: FunctionLocator,
+}
+
+/// retry 子命令：用补救策略重新处理 extract 写下的失败记录
+pub fn run_retry(args: &RetryArgs) -> Result<()> {
+    let output_dir = args.output_dir.clone().unwrap_or_else(|| args.result_dir.join("retry"));
+    if output_dir == args.result_dir {
+        bail!("--output-dir must differ from --result-dir, the failed records are read from there");
+    }
+    fs::create_dir_all(&output_dir)
+        .with_context(|| format!("cannot create output directory {:?}", output_dir))?;
+
+    // CSV 与 JSONL 输入的失败记录分别写在同名的 .csv 与 .jsonl 中
+    let failed_csv_path = args.result_dir.join(FAILED_RECORDS_FILE_NAME);
+    let failed_jsonl_path = failed_csv_path.with_extension("jsonl");
+    let mut inputs = Vec::new();
+    if failed_csv_path.is_file() {
+        inputs.push(RecordSource::open(&failed_csv_path, InputFormat::Csv, args.headers, args.column_map.as_deref())?);
+    }
+    if failed_jsonl_path.is_file() {
+        inputs.push(RecordSource::open(&failed_jsonl_path, InputFormat::Jsonl, false, args.column_map.as_deref())?);
+    }
+    if inputs.is_empty() {
+        bail!("no failed records in {:?}", args.result_dir);
+    }
+    let mut failures = FailureSink::open(&output_dir, inputs[0].headers())?;
+
+    let mut retrier = Retrier {
+        crate_cache: CrateCache::new(&args.cache)?,
+        path_rewriter: PathRewriter::load(args.path_rewrite.as_deref())?,
+        alt_path_rewriters: args
+            .alt_path_rewrites
+            .iter()
+            .map(|rules_path| PathRewriter::load(Some(rules_path)))
+            .collect::<Result<_>>()?,
+        strategies: if args.strategies.is_empty() { ALL_STRATEGIES.to_vec() } else { args.strategies.clone() },
+        line_window: args.line_window,
+        current: None,
+        ast_cache: AstCache::default(),
+        locator: FunctionLocator::new(args.locate_by),
+    };
+    let mut summary = RetrySummary::default();
+    let mut results: Vec<FunctionCommentStatus> = Vec::new();
+    for row in inputs.into_iter().flatten() {
+        let InputRow { raw: record, item_id, record: parsed_record } = match row {
+            Ok(row) => row,
+            Err(e) => {
+                eprintln!("Error reading failed record: {:#}", e);
+                continue;
+            }
+        };
+        summary.retried += 1;
+        let outcome = match parsed_record {
+            Ok(function_record) => retrier.retry(&function_record, &output_dir, &mut failures),
+            Err(e) => Err(Box::new(Failure::new(FailureKind::InvalidRecord, format!("{:#}", e)))),
+        };
+        match outcome {
+            Ok(recovered) => {
+                summary.recovered += 1;
+                if recovered.strategies.is_empty() {
+                    summary.recovered_as_is += 1;
+                }
+                for strategy in &recovered.strategies {
+                    *summary.recovered_by_strategy.entry(*strategy).or_default() += 1;
+                }
+                if results.first().is_some_and(|first: &FunctionCommentStatus| first.crate_name != recovered.status.crate_name) {
+                    write_crate_results(&output_dir, &results[0].crate_name, &results)?;
+                    results.clear();
+                }
+                results.push(recovered.status);
+            }
+            Err(failure) => {
+                summary.still_failed += 1;
+                failures.record(Some(&record), item_id.as_deref(), &failure)?;
+            }
+        }
+    }
+    if let Some(first) = results.first() {
+        write_crate_results(&output_dir, &first.crate_name, &results)?;
+    }
+
+    let summary_path = output_dir.join(SUMMARY_FILE_NAME);
+    let json = serde_json::to_string_pretty(&summary).context("cannot serialize retry summary")?;
+    fs::write(&summary_path, json).with_context(|| format!("cannot write {:?}", summary_path))?;
+    println!(
+        "retried {} records, recovered {}, still failed {}",
+        summary.retried, summary.recovered, summary.still_failed
+    );
+    println!("recovered as is: {}", summary.recovered_as_is);
+    for (strategy, count) in &summary.recovered_by_strategy {
+        println!("recovered by {:?}: {}", strategy, count);
+    }
+    println!("Summary written to {:?}", summary_path);
+    Ok(())
+}
+
+impl Retrier {
+    fn enabled(&self, strategy: RetryStrategy) -> bool {
+        self.strategies.contains(&strategy)
+    }
+
+    /// 按 extract 的流程重新处理一条记录，在原本会失败的步骤上依次尝试启用的策略
+    fn retry(&mut self, record: &FunctionRecord, result_root: &Path, failures: &mut FailureSink) -> Result<Recovered, Box<Failure>> {
+        let mut location = FailureLocation {
+            crate_name: Some(record.crate_name.clone()),
+            crate_version: record.version.clone(),
+            def_path: Some(record.def_path.clone()),
+            file: Some(record.file.clone()),
+            line: Some(record.start_line),
+            column: None,
+        };
+        let mut used = Vec::new();
+
+        let source_route = self.crate_cache.route(&record.file);
+        let source_root = source_route.as_ref().map(|route| route.root.clone());
+        let rel_file = match source_route {
+            Some(route) => route.rel_file,
+            None => self.path_rewriter.rewrite(&record.file),
+        };
+        // 备选规则不依赖 crate 内容，先算好
+        let alt_rel_files: Vec<String> = if self.enabled(RetryStrategy::PathRewrite) {
+            self.alt_path_rewriters
+                .iter()
+                .map(|rewriter| rewriter.rewrite(&record.file))
+                .filter(|alt_rel_file| rel_file_error(alt_rel_file).is_none() && *alt_rel_file != rel_file)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let crate_name = match &source_root {
+            Some(_) => record.crate_name.clone(),
+            None => self
+                .crate_cache
+                .resolve_name(&record.crate_name)
+                .map_err(|e| Box::new(Failure::new(FailureKind::CrateMissing, format!("{:#}", e)).at(&location)))?,
+        };
+        location.crate_name = Some(crate_name.clone());
+
+        let path_rewrite = self.enabled(RetryStrategy::PathRewrite);
+        let fuzzy_line = self.enabled(RetryStrategy::FuzzyLine);
+        let lenient_parse = self.enabled(RetryStrategy::LenientParse);
+        let line_window = self.line_window;
+        self.open(&crate_name, record.version.as_deref(), source_root, result_root, failures);
+        let Retrier { current, ast_cache, locator, .. } = self;
+        let opened = match &current.as_ref().expect("open sets the current crate").opened {
+            Ok(opened) => opened,
+            Err(failure) => return Err(Box::new(failure.clone().at(&location))),
+        };
+        let crate_files = &opened.files;
+
+        // 与 extract 一样先按 def_path 查找，def_path 只指向一处时连同文件一起替代记录中的位置
+        let def_path_sites = locator.def_path_sites(crate_files, ast_cache, &record.def_path, &crate_name);
+        let rel_file = if let [site] = def_path_sites.as_slice() {
+            site.rel_file.to_string_lossy().into_owned()
+        } else if rel_file_error(&rel_file).is_none() && crate_files.exists(Path::new(&rel_file)) {
+            rel_file
+        } else {
+            let alternative = alt_rel_files
+                .into_iter()
+                .find(|alt_rel_file| crate_files.exists(Path::new(alt_rel_file)))
+                .or_else(|| if path_rewrite { match_path_suffix(&crate_files.rust_files(), &record.file) } else { None });
+            match alternative {
+                Some(alternative) => {
+                    used.push(RetryStrategy::PathRewrite);
+                    alternative
+                }
+                None => {
+                    if let Some(error) = rel_file_error(&rel_file) {
+                        return Err(Box::new(Failure::new(FailureKind::PathRewrite, error).at(&location)));
+                    }
+                    location.file = Some(rel_file.clone());
+                    let file_path = crate_files.display_path(Path::new(&rel_file));
+                    return Err(Box::new(Failure::new(FailureKind::FileMissing, format!("file {:?} does not exist", file_path)).at(&location)));
+                }
+            }
+        };
+        location.file = Some(rel_file.clone());
+        let start_line = locator::locate_site(&def_path_sites, &rel_file, record.start_line).map_or(record.start_line, |site| site.start_line);
+        location.line = Some(start_line);
+        let file_path = crate_files.display_path(Path::new(&rel_file));
+        let ParsedSource { source, parsed, functions } = ast_cache.get_or_parse(crate_files, Path::new(&rel_file)).map_err(|e| {
+            Box::new(Failure::new(FailureKind::Io, format!("Failed to read file {:?}: {}", file_path, e)).at(&location))
+        })?;
+        // AstCache 总是逐个解析整体解析失败的文件；只有启用 lenient-parse 时才采用这样的结果
+        if let Some(region) = parsed.skipped.first() {
+            if !lenient_parse {
+                return Err(Box::new(region.failure(&location)));
+            }
+            used.push(RetryStrategy::LenientParse);
+        }
+        let ast = &parsed.file;
+
+        let func = match functions.find(ast, start_line) {
+            Some(func) => func,
+            None => match fuzzy_line.then(|| find_function_near_line(functions, ast, start_line, line_window, &record.def_path)).flatten() {
+                Some(func) => {
+                    used.push(RetryStrategy::FuzzyLine);
+                    func
+                }
+                None => {
+                    // 起始行落在无法解析的区域中时报告解析错误
+                    let failure = match parsed.skipped_at(start_line) {
+                        Some(region) => region.failure(&location),
+                        None => locator::function_not_found(&def_path_sites, start_line, &file_path, &location),
+                    };
+                    return Err(Box::new(failure));
+                }
+            },
+        };
+
+        let module_path = locator.module_path(Path::new(&rel_file), functions, ast, func.name_and_lines().1);
+        let mut status = function_comment_status(&crate_name, record.version.as_deref(), record.def_path.clone(), rel_file, source, &func);
+        status.crate_checksum = opened.checksum.clone();
+        status.module_path = module_path.map(|module_path| full_module_path(&crate_name, &module_path));
+        Ok(Recovered { status, strategies: used })
+    }
+
+    /// 打开记录所在的 crate 作为当前 crate；与上一条记录是同一个 crate 时直接复用，包括上次失败的原因
+    fn open(&mut self, crate_name: &str, crate_version: Option<&str>, source_root: Option<PathBuf>, result_root: &Path, failures: &mut FailureSink) {
+        let reuse = self.current.as_ref().is_some_and(|current| {
+            current.crate_name == crate_name && current.crate_version.as_deref() == crate_version && current.source_root == source_root
+        });
+        if !reuse {
+            let located = match &source_root {
+                Some(root) => Ok(LocatedCrate {
+                    crate_name: crate_name.to_owned(),
+                    location: CrateLocation::Extracted(root.clone()),
+                }),
+                None => self.crate_cache.locate(crate_name, crate_version),
+            };
+            let opened = match located {
+                Ok(located) => self
+                    .crate_cache
+                    .open(&located)
+                    .map_err(|e| Failure::new(FailureKind::of_open_error(&e), format!("{:#}", e))),
+                Err(e) => Err(Failure::new(FailureKind::CrateMissing, format!("{:#}", e))),
+            };
+            let crate_location = FailureLocation {
+                crate_name: Some(crate_name.to_owned()),
+                crate_version: crate_version.map(str::to_owned),
+                ..FailureLocation::default()
+            };
+            if let Err(e) = record_rejections(result_root, failures, &crate_location, &self.crate_cache.take_rejections()) {
+                eprintln!("cannot record unpack rejections: {:#}", e);
+            }
+            if let Some(failure) = opened.as_ref().ok().and_then(|opened| opened.unverifiable_failure(&crate_location)) {
+                if let Err(e) = failures.record(None, None, &failure) {
+                    eprintln!("cannot record unverifiable checksum: {:#}", e);
+                }
+            }
+            self.current = Some(CurrentCrate {
+                crate_name: crate_name.to_owned(),
+                crate_version: crate_version.map(str::to_owned),
+                source_root,
+                opened,
+            });
+            self.ast_cache.clear();
+            self.locator.clear();
+        }
+    }
+}
+
+/// 在起始行上下 line_window 行内查找函数，近的优先；def_path 的末段是标识符时要求函数同名
+fn find_function_near_line<'ast>(functions: &FunctionIndex, ast: &'ast File, start_line: usize, line_window: usize, def_path: &str) -> Option<FunctionMacroType<'ast>> {
+    let wanted_name = def_path
+        .rsplit("::")
+        .next()
+        .filter(|name| !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_'));
+    (1..=line_window)
+        .flat_map(|distance| [start_line.checked_add(distance), start_line.checked_sub(distance)])
+        .flatten()
+        .filter(|line| *line > 0)
+        .filter_map(|line| functions.find(ast, line))
+        .find(|func| wanted_name.is_none_or(|wanted_name| func.name_and_lines().0 == wanted_name))
+}
+
+/// 在 crate 的 .rs 文件中找出与记录路径末尾若干段相同的文件，取匹配段数最多且唯一的一个
+fn match_path_suffix(rust_files: &[PathBuf], file: &str) -> Option<String> {
+    let normalized = file.replace('\\', "/");
+    let record_parts: Vec<&str> = normalized.split('/').filter(|part| !part.is_empty() && *part != ".").collect();
+    let mut best: Option<(usize, &PathBuf)> = None;
+    let mut tied = false;
+    for rust_file in rust_files {
+        let parts: Vec<String> = rust_file
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        if parts.is_empty() || parts.len() > record_parts.len() || !record_parts.ends_with(&parts.iter().map(String::as_str).collect::<Vec<_>>()) {
+            continue;
+        }
+        match best {
+            Some((best_len, _)) if best_len > parts.len() => {}
+            Some((best_len, _)) if best_len == parts.len() => tied = true,
+            _ => {
+                best = Some((parts.len(), rust_file));
+                tied = false;
+            }
+        }
+    }
+    match best {
+        Some((_, rust_file)) if !tied => Some(rust_file.to_string_lossy().replace('\\', "/")),
+        _ => None,
+    }
+}
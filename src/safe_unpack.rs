@@ -0,0 +1,373 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use serde::Serialize;
+use tar::{Archive, Entry, EntryType};
+
+use crate::failure::{Failure, FailureKind, FailureLocation, FailureSink};
+
+const REJECTIONS_FILE_NAME: &str = "unpack_rejections.jsonl";
+
+/// 解压时的大小上限
+#[derive(Debug, Clone, Copy)]
+pub struct UnpackLimits {
+    /// 单个条目的最大字节数，超过的条目被跳过
+    pub max_entry_bytes: u64,
+    /// 一个 crate 中所有条目的最大总字节数，超过时整个 crate 放弃
+    pub max_total_bytes: u64,
+}
+
+/// 被拒绝的归档条目，写入结果目录中的 unpack_rejections.jsonl
+#[derive(Debug, Clone, Serialize)]
+pub struct UnpackRejection {
+    pub archive: PathBuf,
+    pub entry: String,
+    #[serde(flatten)]
+    pub reason: RejectReason,
+}
+
+impl UnpackRejection {
+    /// 写入失败文件的失败，location 是 crate 的位置，文件取被拒绝的条目
+    pub fn failure(&self, location: &FailureLocation) -> Failure {
+        let mut failure = Failure::new(
+            FailureKind::UnpackRejected,
+            format!("rejected entry of {:?}: {}", self.archive, self.reason),
+        )
+        .at(location);
+        failure.location.file = Some(self.entry.clone());
+        failure
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum RejectReason {
+    /// 绝对路径或含有 `..`，会写到目标目录之外
+    EscapesTarget,
+    /// 符号链接或硬链接
+    Link { target: Option<String> },
+    /// 设备文件、FIFO 等非普通文件
+    SpecialFile { entry_type: String },
+    EntryTooLarge { size: u64, limit: u64 },
+    CrateTooLarge { total: u64, limit: u64 },
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::EscapesTarget => f.write_str("path escapes the unpack directory"),
+            RejectReason::Link { target: Some(target) } => write!(f, "link to {}", target),
+            RejectReason::Link { target: None } => f.write_str("link"),
+            RejectReason::SpecialFile { entry_type } => write!(f, "special file of type {}", entry_type),
+            RejectReason::EntryTooLarge { size, limit } => write!(f, "entry of {} bytes exceeds the limit of {} bytes", size, limit),
+            RejectReason::CrateTooLarge { total, limit } => write!(f, "crate of at least {} bytes exceeds the limit of {} bytes", total, limit),
+        }
+    }
+}
+
+/// 逐条检查 .crate 中的条目，只把通过检查的条目交给调用方，并记录所有被拒绝的条目
+#[derive(Debug)]
+pub struct UnpackGuard {
+    limits: UnpackLimits,
+    rejections: Vec<UnpackRejection>,
+}
+
+impl UnpackGuard {
+    pub fn new(limits: UnpackLimits) -> UnpackGuard {
+        UnpackGuard {
+            limits,
+            rejections: Vec::new(),
+        }
+    }
+
+    /// 取出目前为止记录的拒绝原因
+    pub fn take_rejections(&mut self) -> Vec<UnpackRejection> {
+        std::mem::take(&mut self.rejections)
+    }
+
+    /// 遍历归档中 wanted 为 true 的条目；目录与普通文件以检查过的相对路径交给 visit
+    ///
+    /// 不安全的条目被跳过并记录；总大小超过上限时记录原因并返回错误。
+    pub fn for_each_entry(
+        &mut self,
+        archive_path: &Path,
+        wanted: impl Fn(&Path) -> bool,
+        mut visit: impl FnMut(&Path, &mut Entry<GzDecoder<File>>) -> Result<()>,
+    ) -> Result<()> {
+        let file = File::open(archive_path).with_context(|| format!("cannot open file {:?}", archive_path))?;
+        let mut archive = Archive::new(GzDecoder::new(file));
+        let mut total: u64 = 0;
+        for entry in archive.entries().with_context(|| format!("cannot read archive {:?}", archive_path))? {
+            let mut entry = entry.with_context(|| format!("cannot read entry in archive {:?}", archive_path))?;
+            let raw_path = entry
+                .path()
+                .with_context(|| format!("invalid entry path in archive {:?}", archive_path))?
+                .into_owned();
+            if !wanted(&raw_path) {
+                continue;
+            }
+            let entry_name = raw_path.to_string_lossy().into_owned();
+            let entry_type = entry.header().entry_type();
+            let rejection = if !is_contained(&raw_path) {
+                Some(RejectReason::EscapesTarget)
+            } else if entry_type.is_symlink() || entry_type.is_hard_link() {
+                Some(RejectReason::Link {
+                    target: entry.link_name().ok().flatten().map(|target| target.to_string_lossy().into_owned()),
+                })
+            } else if !matches!(entry_type, EntryType::Regular | EntryType::Directory) {
+                Some(RejectReason::SpecialFile {
+                    entry_type: format!("{:?}", entry_type),
+                })
+            } else if entry.size() > self.limits.max_entry_bytes {
+                Some(RejectReason::EntryTooLarge {
+                    size: entry.size(),
+                    limit: self.limits.max_entry_bytes,
+                })
+            } else {
+                None
+            };
+            if let Some(reason) = rejection {
+                self.reject(archive_path, entry_name, reason);
+                continue;
+            }
+            total += entry.size();
+            if total > self.limits.max_total_bytes {
+                self.reject(
+                    archive_path,
+                    entry_name,
+                    RejectReason::CrateTooLarge {
+                        total,
+                        limit: self.limits.max_total_bytes,
+                    },
+                );
+                bail!(
+                    "crate archive {:?} is larger than the unpack limit of {} bytes",
+                    archive_path,
+                    self.limits.max_total_bytes
+                );
+            }
+            visit(&raw_path, &mut entry)?;
+        }
+        Ok(())
+    }
+
+    fn reject(&mut self, archive_path: &Path, entry: String, reason: RejectReason) {
+        println!("reject entry {:?} in {:?}: {:?}", entry, archive_path, reason);
+        self.rejections.push(UnpackRejection {
+            archive: archive_path.to_path_buf(),
+            entry,
+            reason,
+        });
+    }
+}
+
+/// 把 .crate 安全地解压到 target_crate_path 下，返回解压出的源码根目录
+pub fn unpack_crate(guard: &mut UnpackGuard, target_crate_path: &Path, zip_crate_path: &Path) -> Result<PathBuf> {
+    guard.for_each_entry(
+        zip_crate_path,
+        |_| true,
+        |rel_path, entry| {
+            let dest = target_crate_path.join(rel_path);
+            if entry.header().entry_type() == EntryType::Directory {
+                return fs::create_dir_all(&dest).with_context(|| format!("cannot create dir {:?}", dest));
+            }
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent).with_context(|| format!("cannot create dir {:?}", parent))?;
+            }
+            entry.unpack(&dest).with_context(|| format!("cannot unpack {:?}", dest))?;
+            Ok(())
+        },
+    )?;
+    println!("success unzip {:?} to {:?}", zip_crate_path, target_crate_path);
+    let folder_name = zip_crate_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+    Ok(target_crate_path.join(folder_name))
+}
+
+/// 路径只由普通组件构成，拼接到目标目录后不会跑到目录外
+fn is_contained(path: &Path) -> bool {
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// 把拒绝原因逐行追加到结果目录中的 unpack_rejections.jsonl，并作为失败写入 failures
+pub fn record_rejections(result_root: &Path, failures: &mut FailureSink, location: &FailureLocation, rejections: &[UnpackRejection]) -> Result<()> {
+    for rejection in rejections {
+        failures.record(None, None, &rejection.failure(location))?;
+    }
+    append_rejections(result_root, rejections)
+}
+
+/// 把拒绝原因逐行追加到结果目录中的 unpack_rejections.jsonl
+fn append_rejections(result_root: &Path, rejections: &[UnpackRejection]) -> Result<()> {
+    if rejections.is_empty() {
+        return Ok(());
+    }
+    let path = result_root.join(REJECTIONS_FILE_NAME);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("cannot open or create {:?}", path))?;
+    for rejection in rejections {
+        let line = serde_json::to_string(rejection).context("cannot serialize unpack rejection")?;
+        writeln!(file, "{}", line).with_context(|| format!("cannot write {:?}", path))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use tar::{Builder, Header};
+
+    use super::*;
+
+    const LIMITS: UnpackLimits = UnpackLimits { max_entry_bytes: 64, max_total_bytes: 256 };
+
+    /// 测试用的临时目录：<tmp>/safe_unpack_<name>_<pid>，其中 target 是解压目录
+    fn test_dir(name: &str) -> PathBuf {
+        let base = std::env::temp_dir().join(format!("safe_unpack_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        fs::create_dir_all(base.join("target")).unwrap();
+        base
+    }
+
+    /// 直接写入头部中的路径，不经过 tar::Builder 对 `..` 与绝对路径的检查
+    fn write_archive(path: &Path, entries: &[(&str, EntryType, &[u8], Option<&str>)]) {
+        let mut builder = Builder::new(GzEncoder::new(File::create(path).unwrap(), Compression::default()));
+        for (name, entry_type, data, link) in entries {
+            let mut header = Header::new_gnu();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(*entry_type);
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            if let Some(link) = link {
+                header.set_link_name(link).unwrap();
+            }
+            header.set_cksum();
+            builder.append(&header, *data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    /// base 下除 target 与归档以外不应出现任何文件
+    fn assert_nothing_outside_target(base: &Path) {
+        let mut names: Vec<String> = fs::read_dir(base).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+        names.sort();
+        assert_eq!(names, ["dp-0.1.0.crate", "target"]);
+    }
+
+    #[test]
+    fn is_contained_accepts_only_normal_components() {
+        assert!(is_contained(Path::new("dp-0.1.0/src/lib.rs")));
+        assert!(is_contained(Path::new("./dp-0.1.0/src/lib.rs")));
+        assert!(!is_contained(Path::new("dp-0.1.0/../../evil.rs")));
+        assert!(!is_contained(Path::new("/etc/passwd")));
+        assert!(!is_contained(Path::new("..")));
+    }
+
+    #[test]
+    fn rejects_entries_that_escape_the_target() {
+        let base = test_dir("escape");
+        let archive = base.join("dp-0.1.0.crate");
+        let absolute = base.join("absolute.rs");
+        write_archive(
+            &archive,
+            &[
+                ("dp-0.1.0/src/lib.rs", EntryType::Regular, b"pub fn a() {}\n", None),
+                ("dp-0.1.0/../../traversal.rs", EntryType::Regular, b"pub fn evil() {}\n", None),
+                (absolute.to_str().unwrap(), EntryType::Regular, b"pub fn evil() {}\n", None),
+            ],
+        );
+        let mut guard = UnpackGuard::new(LIMITS);
+        let root = unpack_crate(&mut guard, &base.join("target"), &archive).unwrap();
+
+        assert_eq!(root, base.join("target/dp-0.1.0"));
+        assert!(root.join("src/lib.rs").is_file());
+        let rejections = guard.take_rejections();
+        assert_eq!(rejections.len(), 2);
+        assert!(rejections.iter().all(|rejection| matches!(rejection.reason, RejectReason::EscapesTarget)));
+        assert_eq!(rejections[0].entry, "dp-0.1.0/../../traversal.rs");
+        assert_nothing_outside_target(&base);
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn rejects_links_and_special_files() {
+        let base = test_dir("links");
+        let archive = base.join("dp-0.1.0.crate");
+        write_archive(
+            &archive,
+            &[
+                ("dp-0.1.0/src/lib.rs", EntryType::Regular, b"pub fn a() {}\n", None),
+                ("dp-0.1.0/src/symlink.rs", EntryType::Symlink, b"", Some("/etc/passwd")),
+                ("dp-0.1.0/src/hardlink.rs", EntryType::Link, b"", Some("dp-0.1.0/src/lib.rs")),
+                ("dp-0.1.0/src/fifo", EntryType::Fifo, b"", None),
+                ("dp-0.1.0/src/tty", EntryType::Char, b"", None),
+                ("dp-0.1.0/src/disk", EntryType::Block, b"", None),
+            ],
+        );
+        let mut guard = UnpackGuard::new(LIMITS);
+        let root = unpack_crate(&mut guard, &base.join("target"), &archive).unwrap();
+
+        let mut unpacked: Vec<String> = fs::read_dir(root.join("src")).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+        unpacked.sort();
+        assert_eq!(unpacked, ["lib.rs"]);
+        let reasons: Vec<String> = guard.take_rejections().iter().map(|rejection| rejection.reason.to_string()).collect();
+        assert_eq!(
+            reasons,
+            ["link to /etc/passwd", "link to dp-0.1.0/src/lib.rs", "special file of type Fifo", "special file of type Char", "special file of type Block"]
+        );
+        assert_nothing_outside_target(&base);
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn skips_entries_over_the_entry_limit() {
+        let base = test_dir("entry_limit");
+        let archive = base.join("dp-0.1.0.crate");
+        write_archive(
+            &archive,
+            &[
+                ("dp-0.1.0/src/big.rs", EntryType::Regular, &[b'x'; 65], None),
+                ("dp-0.1.0/src/lib.rs", EntryType::Regular, &[b'x'; 64], None),
+            ],
+        );
+        let mut guard = UnpackGuard::new(LIMITS);
+        let root = unpack_crate(&mut guard, &base.join("target"), &archive).unwrap();
+
+        assert!(!root.join("src/big.rs").exists());
+        assert!(root.join("src/lib.rs").is_file());
+        let rejections = guard.take_rejections();
+        assert_eq!(rejections.len(), 1);
+        assert!(matches!(rejections[0].reason, RejectReason::EntryTooLarge { size: 65, limit: 64 }));
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn gives_up_when_the_crate_exceeds_the_total_limit() {
+        let base = test_dir("total_limit");
+        let archive = base.join("dp-0.1.0.crate");
+        let entries: Vec<(String, [u8; 60])> = (0..5).map(|index| (format!("dp-0.1.0/src/m{}.rs", index), [b'x'; 60])).collect();
+        let entries: Vec<(&str, EntryType, &[u8], Option<&str>)> =
+            entries.iter().map(|(name, data)| (name.as_str(), EntryType::Regular, &data[..], None)).collect();
+        write_archive(&archive, &entries);
+        let mut guard = UnpackGuard::new(LIMITS);
+
+        assert!(unpack_crate(&mut guard, &base.join("target"), &archive).is_err());
+        let rejections = guard.take_rejections();
+        assert_eq!(rejections.len(), 1);
+        assert_eq!(rejections[0].entry, "dp-0.1.0/src/m4.rs");
+        assert!(matches!(rejections[0].reason, RejectReason::CrateTooLarge { total: 300, limit: 256 }));
+        assert!(!base.join("target/dp-0.1.0/src/m4.rs").exists());
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
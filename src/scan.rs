@@ -0,0 +1,391 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use quote::ToTokens;
+use serde::Deserialize;
+use syn::visit::{self, Visit};
+use syn::{ForeignItemFn, ImplItemMethod, ItemFn, ItemImpl, ItemMacro, ItemMacro2, ItemMod, ItemTrait, TraitItemMethod};
+
+use crate::cli::{LocalArgs, ScanArgs};
+use crate::crate_source::{split_name_version, CrateCache};
+use crate::ast_cache::{AstCache, ParsedSource};
+use crate::module_tree::ModuleTree;
+use crate::recovery::{SkippedRegion, UnparsedLog};
+use crate::safe_unpack::record_rejections;
+use crate::vfs::CrateFiles;
+use crate::failure::{Failure, FailureKind, FailureLocation, FailureSink};
+use crate::{function_comment_status, write_crate_results, FunctionCommentStatus, FunctionMacroType, Root};
+
+/// scan 子命令：不依赖函数 CSV，读取 crate 列表并提取每个 crate 中全部函数的注释
+pub fn run_scan(args: &ScanArgs) -> Result<()> {
+    let crate_list_data = fs::read_to_string(&args.crate_list)
+        .with_context(|| format!("cannot read crate list {:?}", args.crate_list))?;
+    let crate_list_root: Root = serde_json::from_str(&crate_list_data)
+        .with_context(|| format!("cannot deserialize crate list {:?}", args.crate_list))?;
+    let mut crate_cache = CrateCache::new(&args.cache)?;
+    fs::create_dir_all(&args.result_dir)
+        .with_context(|| format!("cannot create result directory {:?}", args.result_dir))?;
+    let mut failures = FailureSink::open(&args.result_dir, None)?;
+    let mut unparsed_log = UnparsedLog::new(&args.result_dir);
+
+    let crate_count = crate_list_root.crates_list.len();
+    let mut function_count = 0;
+    for (crate_index, entry) in crate_list_root.crates_list.iter().enumerate() {
+        let package = &entry.package;
+        println!("scan crate {}/{}: {} {}", crate_index + 1, crate_count, package.name, package.version);
+        let location = FailureLocation {
+            crate_name: Some(package.name.clone()),
+            crate_version: Some(package.version.clone()),
+            ..FailureLocation::default()
+        };
+        let located = match crate_cache.locate(&package.name, Some(&package.version)) {
+            Ok(located) => located,
+            Err(e) => {
+                failures.record(None, None, &Failure::new(FailureKind::CrateMissing, format!("{:#}", e)).at(&location))?;
+                continue;
+            }
+        };
+        let opened = crate_cache.open(&located);
+        record_rejections(&args.result_dir, &mut failures, &location, &crate_cache.take_rejections())?;
+        let opened = match opened {
+            Ok(opened) => opened,
+            Err(e) => {
+                let failure = Failure::new(FailureKind::of_open_error(&e), format!("{:#}", e)).at(&location);
+                failures.record(None, None, &failure)?;
+                continue;
+            }
+        };
+        let crate_name = located.crate_name;
+        let source_files = opened.files.rust_files();
+        let ScannedSources {
+            mut results,
+            failures: file_failures,
+            skipped,
+        } = scan_sources(&crate_name, Some(&package.version), &opened.files, &source_files, true);
+        for status in &mut results {
+            status.crate_checksum = opened.checksum.clone();
+        }
+        for failure in &file_failures {
+            failures.record(None, None, failure)?;
+        }
+        for (rel_file, regions) in &skipped {
+            unparsed_log.record(&crate_name, Some(&package.version), rel_file, regions)?;
+        }
+        function_count += results.len();
+        if !results.is_empty() {
+            let output_path = write_crate_results(&args.result_dir, &crate_name, &results)?;
+            println!("Results written of {} to {:?}", crate_name, output_path);
+        }
+    }
+    println!(
+        "scanned {} crates, extracted function count {}, failure count {}",
+        crate_count,
+        function_count,
+        failures.count()
+    );
+    Ok(())
+}
+
+/// local 子命令：不需要 crate 缓存或函数 CSV，直接提取本地 .rs 文件或项目目录中全部函数的注释
+pub fn run_local(args: &LocalArgs) -> Result<()> {
+    let path = args.path.as_path();
+    if !path.exists() {
+        bail!("path {:?} does not exist", path);
+    }
+    // 单个文件时以其所在目录为根，输出中的 file 仍是相对路径
+    let source_root = if path.is_file() {
+        path.parent().unwrap_or_else(|| Path::new(""))
+    } else {
+        path
+    };
+    let crate_name = match &args.crate_name {
+        Some(crate_name) => crate_name.clone(),
+        None => local_crate_name(path),
+    };
+    let crate_files = CrateFiles::Dir(source_root.to_path_buf());
+    let source_files = match path.file_name() {
+        Some(file_name) if path.is_file() => vec![PathBuf::from(file_name)],
+        _ => crate_files.rust_files(),
+    };
+    // 单个文件本身就作为 crate 根，def_path 中不再加入由文件名推出的模块
+    let ScannedSources {
+        results,
+        failures,
+        skipped,
+    } = scan_sources(&crate_name, None, &crate_files, &source_files, path.is_dir());
+    for failure in &failures {
+        eprintln!("{}", failure);
+    }
+    for (rel_file, regions) in &skipped {
+        for region in regions {
+            eprintln!("skipped {}:{}-{}: {}", rel_file, region.start_line, region.end_line, region.message);
+        }
+    }
+    match &args.result_dir {
+        Some(result_dir) => {
+            fs::create_dir_all(result_dir)
+                .with_context(|| format!("cannot create result directory {:?}", result_dir))?;
+            let output_path = write_crate_results(result_dir, &crate_name, &results)?;
+            println!("Results written of {} to {:?}", crate_name, output_path);
+        }
+        None => {
+            let json = serde_json::to_string_pretty(&results).context("Failed to serialize to JSON")?;
+            println!("{}", json);
+        }
+    }
+    eprintln!("extracted function count {}, failed files {}", results.len(), failures.len());
+    Ok(())
+}
+
+/// scan_sources 的输出
+struct ScannedSources {
+    results: Vec<FunctionCommentStatus>,
+    /// 无法读取的文件，以及没有任何条目能解析的文件
+    failures: Vec<Failure>,
+    /// 部分条目无法解析的文件：相对路径与跳过的区域
+    skipped: Vec<(String, Vec<SkippedRegion>)>,
+}
+
+/// 收集 source_files 中每个 .rs 文件里所有函数的注释状态
+///
+/// source_files 是相对 crate_files 根的路径；`module_from_path` 为 true 时由 crate 的模块树得出 def_path 中的模块部分，
+/// 不在模块树中的文件（tests、examples 等）由路径推出。
+fn scan_sources(crate_name: &str, crate_version: Option<&str>, crate_files: &CrateFiles, source_files: &[PathBuf], module_from_path: bool) -> ScannedSources {
+    let mut results = Vec::new();
+    let mut failures = Vec::new();
+    let mut skipped = Vec::new();
+    let mut ast_cache = AstCache::default();
+    let module_tree = if module_from_path {
+        ModuleTree::build(crate_files, &mut ast_cache)
+    } else {
+        ModuleTree::default()
+    };
+    for rel_file in source_files {
+        let file_path = crate_files.display_path(rel_file);
+        let rel_file_string = rel_file.to_string_lossy().into_owned();
+        let location = FailureLocation {
+            crate_name: Some(crate_name.to_owned()),
+            crate_version: crate_version.map(str::to_owned),
+            file: Some(rel_file_string.clone()),
+            ..FailureLocation::default()
+        };
+        // 整体解析失败时逐个解析顶层条目，没有任何条目能解析时才算作文件失败
+        let ParsedSource { source, parsed, .. } = match ast_cache.get_or_parse(crate_files, rel_file) {
+            Ok(parsed_source) => parsed_source,
+            Err(e) => {
+                failures.push(Failure::new(FailureKind::Io, format!("Failed to read file {:?}: {}", file_path, e)).at(&location));
+                continue;
+            }
+        };
+        if !parsed.skipped.is_empty() {
+            if parsed.file.items.is_empty() {
+                failures.push(parsed.skipped[0].failure(&location));
+                continue;
+            }
+            skipped.push((rel_file_string.clone(), parsed.skipped.clone()));
+        }
+        let ast = &parsed.file;
+
+        let mut module_path = vec![crate_name.replace('-', "_")];
+        if module_from_path {
+            match module_tree.module_path(rel_file) {
+                Some(file_modules) => module_path.extend(file_modules.iter().cloned()),
+                None => module_path.extend(module_path_of(rel_file)),
+            }
+        }
+        let mut collector = FunctionCollector {
+            path: module_path,
+            functions: Vec::new(),
+        };
+        collector.visit_file(ast);
+        for (def_path, func) in &collector.functions {
+            results.push(function_comment_status(crate_name, crate_version, def_path.clone(), rel_file_string.clone(), source, func));
+        }
+    }
+    ScannedSources {
+        results,
+        failures,
+        skipped,
+    }
+}
+
+/// 由 crate 内的相对路径推出模块路径：src/lib.rs -> []，src/a/mod.rs 与 src/a.rs -> [a]
+fn module_path_of(rel_file: &Path) -> Vec<String> {
+    let mut parts: Vec<String> = rel_file
+        .with_extension("")
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    if parts.first().is_some_and(|first| first == "src") {
+        parts.remove(0);
+    }
+    if parts.last().is_some_and(|last| last == "mod") || parts == ["lib"] || parts == ["main"] {
+        parts.pop();
+    }
+    parts
+}
+
+/// local 未指定 --crate-name 时的 crate 名
+///
+/// 目录优先取 Cargo.toml 中的 `[package].name`，否则取目录名并去掉 `-<version>` 后缀（如 `either-1.9.0`）；
+/// 单个文件取文件名。
+fn local_crate_name(path: &Path) -> String {
+    #[derive(Deserialize)]
+    struct Manifest {
+        package: Option<Package>,
+    }
+    #[derive(Deserialize)]
+    struct Package {
+        name: String,
+    }
+    if path.is_dir() {
+        let manifest = fs::read_to_string(path.join("Cargo.toml"))
+            .ok()
+            .and_then(|text| toml::from_str::<Manifest>(&text).ok());
+        if let Some(Package { name }) = manifest.and_then(|manifest| manifest.package) {
+            return name;
+        }
+    }
+    let Some(full_path) = fs::canonicalize(path).ok() else {
+        return "local".to_owned();
+    };
+    let name = if path.is_file() { full_path.file_stem() } else { full_path.file_name() };
+    match name.and_then(|name| name.to_str()) {
+        Some(name) if path.is_dir() => split_name_version(name).map_or(name, |(crate_name, _)| crate_name).to_owned(),
+        Some(name) => name.to_owned(),
+        None => "local".to_owned(),
+    }
+}
+
+/// 用 syn visitor 收集文件中的所有函数及其路径，不深入函数体内部
+struct FunctionCollector<'ast> {
+    path: Vec<String>,
+    functions: Vec<(String, FunctionMacroType<'ast>)>,
+}
+
+impl<'ast> FunctionCollector<'ast> {
+    fn push(&mut self, ident: &syn::Ident, func: FunctionMacroType<'ast>) {
+        let def_path = format!("{}::{}", self.path.join("::"), ident);
+        self.functions.push((def_path, func));
+    }
+}
+
+impl<'ast> Visit<'ast> for FunctionCollector<'ast> {
+    fn visit_item_fn(&mut self, item_fn: &'ast ItemFn) {
+        self.push(&item_fn.sig.ident, FunctionMacroType::ItemFn(item_fn));
+    }
+
+    /// 与 extract 的索引一致，只收集有名字的宏定义（`macro_rules!`），不收集宏调用
+    fn visit_item_macro(&mut self, item_macro: &'ast ItemMacro) {
+        if let Some(ident) = &item_macro.ident {
+            self.push(ident, FunctionMacroType::ItemMacro(item_macro));
+        }
+    }
+
+    fn visit_item_macro2(&mut self, item_macro2: &'ast ItemMacro2) {
+        self.push(&item_macro2.ident, FunctionMacroType::ItemMacro2(item_macro2));
+    }
+
+    fn visit_foreign_item_fn(&mut self, foreign_item_fn: &'ast ForeignItemFn) {
+        self.push(&foreign_item_fn.sig.ident, FunctionMacroType::ForeignItemFn(foreign_item_fn));
+    }
+
+    fn visit_impl_item_method(&mut self, impl_item_method: &'ast ImplItemMethod) {
+        self.push(&impl_item_method.sig.ident, FunctionMacroType::ImplItemMethod(impl_item_method));
+    }
+
+    fn visit_trait_item_method(&mut self, trait_item_method: &'ast TraitItemMethod) {
+        self.push(&trait_item_method.sig.ident, FunctionMacroType::TraitItemMethod(trait_item_method));
+    }
+
+    fn visit_item_mod(&mut self, item_mod: &'ast ItemMod) {
+        self.path.push(item_mod.ident.to_string());
+        visit::visit_item_mod(self, item_mod);
+        self.path.pop();
+    }
+
+    fn visit_item_impl(&mut self, item_impl: &'ast ItemImpl) {
+        let self_ty = type_name(&item_impl.self_ty);
+        let segment = match &item_impl.trait_ {
+            Some((_, trait_path, _)) => format!("<{} as {}>", self_ty, path_name(trait_path)),
+            None => self_ty,
+        };
+        self.path.push(segment);
+        for impl_item in &item_impl.items {
+            self.visit_impl_item(impl_item);
+        }
+        self.path.pop();
+    }
+
+    fn visit_item_trait(&mut self, item_trait: &'ast ItemTrait) {
+        self.path.push(item_trait.ident.to_string());
+        for trait_item in &item_trait.items {
+            self.visit_trait_item(trait_item);
+        }
+        self.path.pop();
+    }
+}
+
+/// 路径去掉泛型参数后的名称，例如 `std::fmt::Display`
+fn path_name(path: &syn::Path) -> String {
+    path.segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+/// impl 块 self 类型的名称，普通路径类型去掉泛型参数，其余类型保留源码文本
+fn type_name(ty: &syn::Type) -> String {
+    match ty {
+        syn::Type::Path(type_path) => path_name(&type_path.path),
+        syn::Type::Reference(reference) => format!("&{}", type_name(&reference.elem)),
+        other => other.to_token_stream().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function_index::FunctionIndex;
+
+    #[test]
+    fn collected_functions_are_found_by_the_index_at_their_start_line() {
+        let source = concat!(
+            "macro_rules! named { () => {} }\n",
+            "lazy_static! { static ref X: u8 = 1; }\n",
+            "mod inner {\n",
+            "    pub fn f() {\n",
+            "        fn nested() {}\n",
+            "    }\n",
+            "    impl S { fn m(&self) {} }\n",
+            "}\n",
+            "extern \"C\" {\n",
+            "    fn a();\n",
+            "    fn b();\n",
+            "}\n",
+            "trait T { fn t() {} }\n",
+        );
+        let ast = syn::parse_file(source).unwrap();
+        let mut collector = FunctionCollector {
+            path: vec!["c".to_owned()],
+            functions: Vec::new(),
+        };
+        collector.visit_file(&ast);
+        let def_paths: Vec<&str> = collector.functions.iter().map(|(def_path, _)| def_path.as_str()).collect();
+        assert_eq!(def_paths, ["c::named", "c::inner::f", "c::inner::S::m", "c::a", "c::b", "c::T::t"]);
+
+        // scan 的输出可以直接作为 extract 的输入：每个函数的起始行都能在索引中找到它自己
+        let index = FunctionIndex::build(&ast);
+        for (def_path, func) in &collector.functions {
+            let (_, start_line, _) = func.name_and_lines();
+            let found = index.find(&ast, start_line).map(|found| found.name_and_lines());
+            assert_eq!(found, Some(func.name_and_lines()), "{}", def_path);
+        }
+        assert!(index.find(&ast, 2).is_none(), "macro invocations are not functions");
+    }
+}
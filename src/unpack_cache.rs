@@ -0,0 +1,212 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+pub const UNPACK_INDEX_FILE_NAME: &str = "unpack_cache_index.json";
+
+/// 已解压 crate 目录的持久索引，按字节预算做 LRU 淘汰
+///
+/// 解压出的目录在多次运行之间保留，同一个 crate 再次出现时直接复用；
+/// 所有目录的总大小超过预算时，从最久未使用的目录开始删除，正在使用的目录不会被删除。
+#[derive(Debug)]
+pub struct UnpackCache {
+    index_path: PathBuf,
+    budget: Option<u64>,
+    index: UnpackIndex,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UnpackIndex {
+    /// 每次使用加一，作为 LRU 的逻辑时钟
+    clock: u64,
+    entries: Vec<UnpackEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct UnpackEntry {
+    path: PathBuf,
+    bytes: u64,
+    last_used: u64,
+}
+
+impl UnpackCache {
+    /// 打开 index_dir 中的索引，丢弃已经不存在的目录
+    pub fn open(index_dir: &Path, budget: Option<u64>) -> Result<UnpackCache> {
+        let index_path = index_dir.join(UNPACK_INDEX_FILE_NAME);
+        let mut index: UnpackIndex = match fs::read_to_string(&index_path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                println!("ignore broken unpack cache index {:?}: {}", index_path, e);
+                UnpackIndex::default()
+            }),
+            Err(_) => UnpackIndex::default(),
+        };
+        index.entries.retain(|entry| entry.path.is_dir());
+        Ok(UnpackCache {
+            index_path,
+            budget,
+            index,
+        })
+    }
+
+    /// 返回 crate_dir，目录不存在时调用 unpack 解压；已有但不在索引中的目录（例如以前的运行留下的）直接收编
+    pub fn get_or_unpack(&mut self, crate_dir: &Path, unpack: impl FnOnce() -> Result<()>) -> Result<PathBuf> {
+        if !crate_dir.is_dir() {
+            unpack()?;
+            if !crate_dir.is_dir() {
+                bail!("unpacking did not produce {:?}", crate_dir);
+            }
+        } else {
+            println!("reuse unpacked crate {:?}", crate_dir);
+        }
+        self.index.clock += 1;
+        let clock = self.index.clock;
+        match self.index.entries.iter_mut().find(|entry| entry.path == crate_dir) {
+            Some(entry) => entry.last_used = clock,
+            None => self.index.entries.push(UnpackEntry {
+                path: crate_dir.to_path_buf(),
+                bytes: dir_size(crate_dir),
+                last_used: clock,
+            }),
+        }
+        self.evict(crate_dir);
+        self.save()?;
+        Ok(crate_dir.to_path_buf())
+    }
+
+    /// 超出预算时按 LRU 删除目录，keep 是当前正在使用的目录
+    fn evict(&mut self, keep: &Path) {
+        let Some(budget) = self.budget else {
+            return;
+        };
+        self.index.entries.sort_by_key(|entry| entry.last_used);
+        let mut total: u64 = self.index.entries.iter().map(|entry| entry.bytes).sum();
+        self.index.entries.retain(|entry| {
+            if total <= budget || entry.path == keep {
+                return true;
+            }
+            match fs::remove_dir_all(&entry.path) {
+                Ok(()) => {
+                    println!("evict unpacked crate {:?} ({} bytes)", entry.path, entry.bytes);
+                    total -= entry.bytes;
+                    false
+                }
+                Err(e) => {
+                    println!("failed to evict {:?}: {}", entry.path, e);
+                    true
+                }
+            }
+        });
+    }
+
+    fn save(&self) -> Result<()> {
+        let json = serde_json::to_string_pretty(&self.index).context("cannot serialize unpack cache index")?;
+        fs::write(&self.index_path, json).with_context(|| format!("cannot write unpack cache index {:?}", self.index_path))
+    }
+}
+
+/// 目录中所有文件的总字节数
+fn dir_size(dir: &Path) -> u64 {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|meta| meta.is_file())
+        .map(|meta| meta.len())
+        .sum()
+}
+
+/// 解析 `500M`、`2G`、`1048576` 这样的字节数
+pub fn parse_byte_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => text.split_at(index),
+        None => (text, ""),
+    };
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        other => return Err(format!("unknown size unit {:?}", other)),
+    };
+    let number: u64 = number.parse().map_err(|e| format!("invalid size {:?}: {}", text, e))?;
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size {:?} is too large", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("unpack_cache_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// 模拟解压：写出一个 100 字节的文件
+    fn unpack_into(crate_dir: &Path) -> impl FnOnce() -> Result<()> + '_ {
+        move || {
+            fs::create_dir_all(crate_dir)?;
+            fs::write(crate_dir.join("lib.rs"), [b' '; 100])?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_used_directories_over_budget() {
+        let dir = cache_dir("lru");
+        let [a, b, c, d] = ["a-1.0.0", "b-1.0.0", "c-1.0.0", "d-1.0.0"].map(|name| dir.join(name));
+        let mut cache = UnpackCache::open(&dir, Some(250)).unwrap();
+        cache.get_or_unpack(&a, unpack_into(&a)).unwrap();
+        cache.get_or_unpack(&b, unpack_into(&b)).unwrap();
+        // 再次使用 a，b 成为最久未使用的目录
+        cache.get_or_unpack(&a, || panic!("a is already unpacked")).unwrap();
+        cache.get_or_unpack(&c, unpack_into(&c)).unwrap();
+        assert!(a.is_dir() && !b.exists() && c.is_dir());
+
+        // 索引在运行之间保留，使用顺序也一起保留
+        let mut cache = UnpackCache::open(&dir, Some(250)).unwrap();
+        cache.get_or_unpack(&d, unpack_into(&d)).unwrap();
+        assert!(!a.exists() && c.is_dir() && d.is_dir());
+
+        // 当前使用的目录即使单独超出预算也不会被删除
+        let mut cache = UnpackCache::open(&dir, Some(50)).unwrap();
+        cache.get_or_unpack(&d, || panic!("d is already unpacked")).unwrap();
+        assert!(!c.exists() && d.is_dir());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reuses_directories_left_by_earlier_runs() {
+        let dir = cache_dir("reuse");
+        let crate_dir = dir.join("a-1.0.0");
+        unpack_into(&crate_dir)().unwrap();
+        let mut cache = UnpackCache::open(&dir, None).unwrap();
+        let reused = cache.get_or_unpack(&crate_dir, || panic!("the directory already exists")).unwrap();
+        assert_eq!(reused, crate_dir);
+        let index: UnpackIndex = serde_json::from_str(&fs::read_to_string(dir.join(UNPACK_INDEX_FILE_NAME)).unwrap()).unwrap();
+        assert_eq!(index.entries.len(), 1);
+        assert_eq!((index.entries[0].bytes, index.entries[0].last_used), (100, 1));
+
+        // 解压没有产生目录时报错
+        let missing = dir.join("b-1.0.0");
+        assert!(cache.get_or_unpack(&missing, || Ok(())).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parse_byte_size_accepts_units() {
+        assert_eq!(parse_byte_size("1048576"), Ok(1 << 20));
+        assert_eq!(parse_byte_size("500M"), Ok(500 << 20));
+        assert_eq!(parse_byte_size(" 2 GiB "), Ok(2 << 30));
+        assert!(parse_byte_size("3X").is_err());
+        assert!(parse_byte_size("99999999999T").is_err());
+    }
+}
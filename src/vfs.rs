@@ -0,0 +1,219 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use tar::EntryType;
+use walkdir::WalkDir;
+
+use crate::safe_unpack::UnpackGuard;
+
+/// crate 源码的只读视图：磁盘上的目录，或者直接从 .crate 归档读入内存的 .rs 文件
+///
+/// 所有按 rel_file 的查找都经过这里，调用方不需要关心源码是否真的解压到了磁盘上。
+#[derive(Debug)]
+pub enum CrateFiles {
+    Dir(PathBuf),
+    Tarball {
+        archive: PathBuf,
+        /// 去掉归档顶层 `<name>-<version>/` 后的相对路径 -> 文件内容
+        files: BTreeMap<PathBuf, Vec<u8>>,
+    },
+}
+
+impl CrateFiles {
+    /// 从 gzip tar 流中读出所有 .rs 文件，不写磁盘；条目先经过 guard 的安全检查
+    pub fn load_tarball(guard: &mut UnpackGuard, archive: &Path) -> Result<CrateFiles> {
+        let mut files = BTreeMap::new();
+        guard.for_each_entry(
+            archive,
+            |entry_path| entry_path.extension().is_some_and(|ext| ext == "rs"),
+            |entry_path, entry| {
+                if entry.header().entry_type() != EntryType::Regular {
+                    return Ok(());
+                }
+                // 顶层目录是 `<name>-<version>`，其余部分才是 crate 内的相对路径
+                let Some(rel_file) = normalize(&entry_path.components().skip(1).collect::<PathBuf>()) else {
+                    return Ok(());
+                };
+                let mut content = Vec::with_capacity(entry.size() as usize);
+                entry
+                    .read_to_end(&mut content)
+                    .with_context(|| format!("cannot read {:?} in archive {:?}", entry_path, archive))?;
+                files.insert(rel_file, content);
+                Ok(())
+            },
+        )?;
+        println!("loaded {} source files from {:?}", files.len(), archive);
+        Ok(CrateFiles::Tarball {
+            archive: archive.to_path_buf(),
+            files,
+        })
+    }
+
+    /// 输出信息中使用的根路径：源码目录或归档文件
+    pub fn root(&self) -> &Path {
+        match self {
+            CrateFiles::Dir(root) => root,
+            CrateFiles::Tarball { archive, .. } => archive,
+        }
+    }
+
+    /// 输出信息中使用的文件路径，归档中的文件显示为 `<archive>/<rel_file>`
+    pub fn display_path(&self, rel_file: &Path) -> PathBuf {
+        self.root().join(rel_file)
+    }
+
+    pub fn exists(&self, rel_file: &Path) -> bool {
+        match self {
+            CrateFiles::Dir(root) => normalize(rel_file).is_some_and(|rel_file| root.join(rel_file).is_file()),
+            CrateFiles::Tarball { files, .. } => normalize(rel_file).is_some_and(|rel_file| files.contains_key(&rel_file)),
+        }
+    }
+
+    pub fn read_to_string(&self, rel_file: &Path) -> io::Result<String> {
+        match self {
+            CrateFiles::Dir(root) => {
+                let rel_file = normalize(rel_file)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "file path leaves the crate directory"))?;
+                fs::read_to_string(root.join(rel_file))
+            }
+            CrateFiles::Tarball { files, .. } => {
+                let content = normalize(rel_file)
+                    .and_then(|rel_file| files.get(&rel_file))
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "file not found in crate archive"))?;
+                String::from_utf8(content.clone()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+        }
+    }
+
+    /// crate 中所有 .rs 文件的相对路径，按路径排序，跳过 target 与隐藏目录
+    pub fn rust_files(&self) -> Vec<PathBuf> {
+        match self {
+            CrateFiles::Dir(root) => WalkDir::new(root)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_entry(|entry| entry.depth() == 0 || !is_skipped_dir(entry))
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().is_file())
+                .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "rs"))
+                .map(|entry| entry.path().strip_prefix(root).unwrap_or(entry.path()).to_path_buf())
+                .collect(),
+            CrateFiles::Tarball { files, .. } => files
+                .keys()
+                .filter(|rel_file| {
+                    rel_file
+                        .parent()
+                        .into_iter()
+                        .flat_map(Path::components)
+                        .all(|component| !is_skipped_name(&component.as_os_str().to_string_lossy()))
+                })
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// 按字面消去 `.` 与 `..`，使同一个文件只有一种写法；超出 crate 根或是绝对路径时返回 None
+///
+/// crate 内按 rel_file 的查找与模块树中 `#[path]` 的解析共用这一规则。
+pub fn normalize(rel_file: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in rel_file.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
+
+/// 构建产物与 .git 等隐藏目录中没有需要提取的源码
+fn is_skipped_dir(entry: &walkdir::DirEntry) -> bool {
+    entry.file_type().is_dir() && entry.file_name().to_str().is_some_and(is_skipped_name)
+}
+
+fn is_skipped_name(name: &str) -> bool {
+    name == "target" || name.starts_with('.')
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::*;
+    use crate::safe_unpack::UnpackLimits;
+
+    fn write_crate_archive(path: &Path, files: &[(&str, &str)]) {
+        let mut builder = tar::Builder::new(GzEncoder::new(fs::File::create(path).unwrap(), Compression::default()));
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn normalize_resolves_dots_within_the_root() {
+        assert_eq!(normalize(Path::new("./src/a/../lib.rs")), Some(PathBuf::from("src/lib.rs")));
+        assert_eq!(normalize(Path::new("src/lib.rs")), Some(PathBuf::from("src/lib.rs")));
+        assert_eq!(normalize(Path::new("src/../../lib.rs")), None);
+        assert_eq!(normalize(Path::new("/src/lib.rs")), None);
+    }
+
+    #[test]
+    fn tarball_lookup_uses_paths_below_the_top_directory() {
+        let archive = std::env::temp_dir().join(format!("vfs_tarball_{}.crate", std::process::id()));
+        write_crate_archive(
+            &archive,
+            &[
+                ("dp-0.1.0/src/lib.rs", "pub fn a() {}\n"),
+                ("dp-0.1.0/src/util/mod.rs", "pub fn b() {}\n"),
+                ("dp-0.1.0/target/debug/build.rs", "fn main() {}\n"),
+                ("dp-0.1.0/.cargo/config.rs", ""),
+                ("dp-0.1.0/README.md", "# dp\n"),
+            ],
+        );
+        let mut guard = UnpackGuard::new(UnpackLimits { max_entry_bytes: 1024, max_total_bytes: 4096 });
+        let files = CrateFiles::load_tarball(&mut guard, &archive).unwrap();
+        fs::remove_file(&archive).unwrap();
+
+        assert!(files.exists(Path::new("src/lib.rs")));
+        assert!(files.exists(Path::new("./src/util/../lib.rs")));
+        assert!(!files.exists(Path::new("dp-0.1.0/src/lib.rs")));
+        assert!(!files.exists(Path::new("../dp-0.1.0/src/lib.rs")));
+        assert!(!files.exists(Path::new("README.md")));
+        assert_eq!(files.read_to_string(Path::new("src/util/mod.rs")).unwrap(), "pub fn b() {}\n");
+        assert_eq!(files.read_to_string(Path::new("/src/lib.rs")).unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(files.rust_files(), [PathBuf::from("src/lib.rs"), PathBuf::from("src/util/mod.rs")]);
+        assert_eq!(files.display_path(Path::new("src/lib.rs")), archive.join("src/lib.rs"));
+    }
+
+    #[test]
+    fn dir_lookup_stays_inside_the_crate_directory() {
+        let base = std::env::temp_dir().join(format!("vfs_dir_{}", std::process::id()));
+        let root = base.join("dp");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/lib.rs"), "pub fn a() {}\n").unwrap();
+        fs::write(base.join("outside.rs"), "pub fn evil() {}\n").unwrap();
+        let files = CrateFiles::Dir(root.clone());
+
+        assert!(files.exists(Path::new("src/../src/lib.rs")));
+        assert!(!files.exists(Path::new("../outside.rs")));
+        assert!(!files.exists(&base.join("outside.rs")));
+        assert!(files.read_to_string(Path::new("../outside.rs")).is_err());
+        assert!(files.read_to_string(&base.join("outside.rs")).is_err());
+        assert_eq!(files.rust_files(), [PathBuf::from("src/lib.rs")]);
+        fs::remove_dir_all(&base).unwrap();
+    }
+}